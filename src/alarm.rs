@@ -0,0 +1,195 @@
+use std::{
+    env, fmt,
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::TryRecvError,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{Local, NaiveDateTime, NaiveTime};
+
+use crate::{
+    commands::Command,
+    config::Config,
+    l10n::{messages::Message, L10n},
+    matcher::BigAction,
+};
+
+/// The time of the day at which `legacylisten` starts playing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlarmTime(NaiveTime);
+
+impl FromStr for AlarmTime
+{
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        NaiveTime::parse_from_str(s.trim(), "%H:%M").map(Self)
+    }
+}
+
+impl fmt::Display for AlarmTime
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error>
+    {
+        write!(f, "{}", self.0.format("%H:%M"))
+    }
+}
+
+impl AlarmTime
+{
+    // When it's that time the next time.
+    fn next(self) -> NaiveDateTime
+    {
+        let now = Local::now().naive_local();
+        let mut alarm = now.date().and_time(self.0);
+        if alarm <= now
+        {
+            alarm += chrono::Duration::days(1);
+        }
+
+        alarm
+    }
+}
+
+/// Reads the alarm time from the command line (`--alarm 7:30` or
+/// `--alarm=7:30`); it takes precedence over the conffile.
+pub fn alarm_from_args(l10n: L10n) -> Option<AlarmTime>
+{
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next()
+    {
+        let value = if arg == "--alarm"
+        {
+            args.next().unwrap_or_default()
+        }
+        else if let Some(value) = arg.strip_prefix("--alarm=")
+        {
+            value.to_string()
+        }
+        else
+        {
+            continue;
+        };
+
+        match value.parse()
+        {
+            Ok(time) => return Some(time),
+            Err(_) => l10n.write(Message::InvalidAlarm(value)),
+        }
+    }
+
+    None
+}
+
+/// Makes the volume go up slowly after the alarm went off.
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp
+{
+    start: Instant,
+    len: Duration,
+}
+
+impl Ramp
+{
+    /// What the volume has to be multiplied with right now.
+    pub fn fade(&self) -> f32
+    {
+        if self.len.is_zero()
+        {
+            return 1.0;
+        }
+
+        (self.start.elapsed().as_secs_f32() / self.len.as_secs_f32()).min(1.0)
+    }
+
+    fn done(&self) -> bool
+    {
+        self.start.elapsed() >= self.len
+    }
+}
+
+// Minutes, since it's usually hours until the alarm goes off.
+fn minutes_left(end: NaiveDateTime) -> f64
+{
+    let left = (end - Local::now().naive_local())
+        .to_std()
+        .unwrap_or_default();
+
+    (left.as_secs_f64() / 60.0).ceil()
+}
+
+/// Waits until the alarm goes off if there is one.  Nothing is read
+/// in the meantime and the only command that does anything is
+/// quitting.
+pub fn wait_for_alarm(config: &mut Config) -> BigAction
+{
+    let conffile = &config.arc_config.conffile;
+    let Some(time) = conffile.alarm
+    else
+    {
+        return BigAction::Nothing;
+    };
+    let ramp_len = Duration::from_millis(conffile.alarm_ramp_ms);
+    let songs = conffile.alarm_songs.clone();
+
+    // The wall clock, since the monotonic one stops while the computer
+    // is suspended.
+    let end = time.next();
+    config
+        .l10n
+        .write(Message::AlarmWaiting(time.to_string(), minutes_left(end)));
+
+    while Local::now().naive_local() < end
+    {
+        match config.rx.try_recv()
+        {
+            Ok(Command::Quit) => return Command::Quit.get_handler()(config),
+            Ok(_) => config
+                .l10n
+                .write(Message::AlarmWaiting(time.to_string(), minutes_left(end))),
+            Err(TryRecvError::Disconnected) =>
+            {
+                config.l10n.write(Message::CommandReadingProblem);
+                return BigAction::Quit;
+            }
+            Err(TryRecvError::Empty) =>
+            {}
+        }
+        // Nothing is playing yet.
+        if config.rx_control.try_recv().is_ok()
+        {
+            let _ = config.tx_paused.send(true);
+            let _ = config.tx_path.send((PathBuf::new(), None));
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    config.l10n.write(Message::AlarmGoingOff);
+    config.alarm_ramp = Some(Ramp {
+        start: Instant::now(),
+        len: ramp_len,
+    });
+    config.only_songs = songs;
+
+    BigAction::Nothing
+}
+
+/// Raises the volume until the ramp after the alarm is over.
+pub fn ramp_up(config: &mut Config)
+{
+    if let Some(ramp) = config.alarm_ramp
+    {
+        if ramp.done()
+        {
+            config.alarm_ramp = None;
+            // The wake-up songs are only for waking up.
+            config.only_songs = None;
+        }
+        config.apply_volume();
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use lofty::picture::{MimeType, Picture};
+
+use crate::{config::ArcConfig, metadata::Metadata};
+
+// In `config_dir`.
+const ART_CACHE: &str = "art";
+
+// Images in the directory of a song that are taken as its cover; the
+// earlier names are preferred.
+const SIDECAR_NAMES: [&str; 5] = ["cover", "folder", "front", "album", "albumart"];
+const SIDECAR_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "webp", "gif", "bmp"];
+
+// FNV-1a; the names of the cached pictures have to stay the same
+// between versions of `legacylisten`.
+fn hash(data: &[u8]) -> u64
+{
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// So that image viewers recognize the file.
+fn extension(picture: &Picture) -> &'static str
+{
+    match picture.mime_type()
+    {
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Jpeg) => "jpg",
+        Some(MimeType::Tiff) => "tiff",
+        Some(MimeType::Bmp) => "bmp",
+        Some(MimeType::Gif) => "gif",
+        _ => "art",
+    }
+}
+
+// Deletes the least recently used pictures until the cache is small
+// enough again; `keep` is never deleted.
+fn evict(dir: &Path, keep: &Path, limit: u64)
+{
+    let Ok(entries) = fs::read_dir(dir)
+    else
+    {
+        return;
+    };
+
+    let mut pictures: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    let mut size: u64 = pictures.iter().map(|(_, len, _)| len).sum();
+
+    pictures.sort_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in pictures
+    {
+        if size <= limit
+        {
+            break;
+        }
+        if path != keep && fs::remove_file(&path).is_ok()
+        {
+            size -= len;
+        }
+    }
+}
+
+// Stores an embedded picture in the cache; every picture is only
+// there once, however many songs have it.
+fn cached(picture: &Picture, config: &ArcConfig) -> Option<PathBuf>
+{
+    let dir = config.config_dir.join(ART_CACHE);
+    let path = dir.join(format!(
+        "{:016x}.{}",
+        hash(picture.data()),
+        extension(picture)
+    ));
+
+    if path.exists()
+    {
+        // Marks it as recently used.
+        let _ = File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+    }
+    else
+    {
+        fs::create_dir_all(&dir).ok()?;
+        fs::write(&path, picture.data()).ok()?;
+        evict(&dir, &path, config.conffile.art_cache_size * 1024 * 1024);
+    }
+
+    Some(path)
+}
+
+// An image like `folder.jpg` or `cover.png` next to the song.
+fn sidecar(song: &Path) -> Option<PathBuf>
+{
+    let rank = |path: &Path| {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        let stem = path.file_stem()?.to_str()?.to_lowercase();
+
+        SIDECAR_EXTENSIONS
+            .contains(&extension.as_str())
+            .then(|| SIDECAR_NAMES.iter().position(|name| *name == stem))
+            .flatten()
+    };
+
+    fs::read_dir(song.parent()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| Some((rank(&path)?, path)))
+        .min()
+        .map(|(_, path)| path)
+}
+
+/// Where the cover of a song is: its embedded picture (taken out into
+/// the art cache) or else an image like `cover.jpg` next to it.
+pub fn cover(song: &Path, metadata: Option<&Metadata>, config: &ArcConfig) -> Option<PathBuf>
+{
+    metadata
+        .and_then(|metadata| metadata.pictures.first())
+        .and_then(|picture| cached(picture, config))
+        .or_else(|| sidecar(song))
+}
+
+/// Deletes the pictures older versions wrote into `config_dir` (one
+/// for every song), since they are now in the art cache.
+pub fn remove_old_art(config: &ArcConfig)
+{
+    let Ok(entries) = fs::read_dir(&config.config_dir)
+    else
+    {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok)
+    {
+        if entry.file_name().to_string_lossy().starts_with("icon.art")
+        {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
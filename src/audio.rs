@@ -3,34 +3,110 @@ use std::{
     io::BufReader,
     path::Path,
     sync::{
-        atomic::Ordering,
-        mpsc::{sync_channel, Receiver},
-        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+        Arc, Mutex,
     },
     thread,
     time::Duration,
 };
 
-use id3::{Tag, TagLike};
 use mp3_metadata::read_from_file;
 use ogg_metadata::{read_format, AudioMetadata, OggFormat};
-use rodio::{Decoder, Source};
+use rodio::Source;
 
 use crate::{
-    buffer::Buffer,
     config::ArcConfig,
+    decoder::SongDecoder,
+    equalizer::{Equalizer, Preset},
     err::Error,
     l10n::{messages::Message, L10n},
+    lyrics::format_lyrics,
+    metadata::Metadata,
 };
 
-pub struct ChannelSource
+// Everything that belongs to one song and has to be known before (or
+// independently of whether) it is the one currently playing.
+pub struct SongState
 {
+    id: usize,
     channels: u16,
     sample_rate: u32,
-    data_rx: Receiver<(usize, i16)>,
+    // In samples; zero if unknown.
+    len: AtomicUsize,
+    // One of the `PHASE_*` constants; a single atomic, so that a song
+    // can't be started and cancelled at the same time.
+    phase: AtomicU8,
+    decoded: AtomicBool,
+    // Set when the `ChannelAudio` is dropped, i.e. when this song
+    // isn't the current one anymore (or never will be).
+    abandoned: AtomicBool,
+    // All in samples; `fade_out_start` is `usize::MAX` as long as the
+    // song doesn't fade out.
+    fade_in: AtomicUsize,
+    fade_out_start: AtomicUsize,
+    fade_out_len: AtomicUsize,
+    // In samples; the part of the song that is played.  `end` is
+    // `usize::MAX` if the song isn't cut at the end.
+    start: AtomicUsize,
+    end: AtomicUsize,
+    // Where playing begins this time (in samples); reset to zero as
+    // soon as it's reached, so that it's possible to seek back.
+    resume: AtomicUsize,
+    // Where the song isn't silent (also in samples), as soon as it's
+    // known.
+    silence: Mutex<Option<(usize, usize)>>,
+    // The equalizer preset; `equalizer_changed` tells the
+    // `ChannelSource` to pick it up.
+    equalizer: Mutex<Option<Arc<Preset>>>,
+    equalizer_changed: AtomicBool,
+    // In samples; `usize::MAX` if unset.  From `loop_start` on the
+    // `ChannelSource` remembers what it plays and as soon as it
+    // reaches `loop_end` it plays that again.
+    loop_start: AtomicUsize,
+    loop_end: AtomicUsize,
+    // Set when seeking, so that a loop which is still played isn't
+    // finished first.
+    loop_dropped: AtomicBool,
+    // Where to go inside the loop (in samples) without seeking the
+    // decoder, since it's still in memory; `usize::MAX` if nowhere.
+    loop_seek: AtomicUsize,
+    // Where the last seek goes to (in samples) until it's reached;
+    // `usize::MAX` if there's none.  The samples from before that are
+    // still on their way are dropped, so that `ArcConfig::current_pos`
+    // doesn't jump back for a moment.
+    seeking: AtomicUsize,
+    // How many samples of it were actually played; unlike the
+    // position this doesn't grow when seeking forward.
+    played: AtomicUsize,
+}
+
+const PHASE_PENDING: u8 = 0;
+const PHASE_STARTED: u8 = 1;
+const PHASE_CANCELLED: u8 = 2;
+
+pub struct ChannelSource
+{
+    data_rx: Receiver<(usize, f32)>,
+    state: Arc<SongState>,
     config: Arc<ArcConfig>,
+    equalizer: Option<Equalizer>,
+    // The samples from `SongState::loop_start` on, so that the loop
+    // can be played without reading the song again; `looped` is the
+    // next one of them to be played while looping.
+    loop_buffer: Vec<f32>,
+    loop_buffer_start: usize,
+    recording_from: usize,
+    looped: Option<usize>,
+    // The sample after the loop, which is played as soon as looping
+    // stops.
+    held: Option<(usize, f32)>,
 }
 
+/// Longer parts of a song can't be looped, since they would take too
+/// much memory.
+pub const MAX_LOOP: Duration = Duration::from_secs(5 * 60);
+
 // I think it sounds better.
 #[allow(clippy::module_name_repetitions)]
 pub struct ChannelAudio
@@ -38,13 +114,75 @@ pub struct ChannelAudio
     pub sample_rate: u32,
     pub inner: Option<ChannelSource>,
     pub config: Arc<ArcConfig>,
+    pub seek_tx: Option<Sender<usize>>,
+    pub state: Option<Arc<SongState>>,
 }
 
-fn get_size<P: AsRef<Path>>(path: P) -> Result<usize, Error>
+// How many samples are decoded between checks whether the length is
+// still needed.
+const SIZE_CHECK_INTERVAL: usize = 64 * 1024;
+
+// Counts the samples of the song; gives up as soon as the song is
+// abandoned.
+fn get_size<P: AsRef<Path>>(path: P, config: Arc<ArcConfig>, state: &SongState) -> Option<usize>
 {
-    let decoder2 = Decoder::new(Buffer::new(File::open(path)?)?)?;
+    let mut len = 0;
 
-    Ok(decoder2.count())
+    for _ in SongDecoder::new(path, config).ok()?
+    {
+        if len % SIZE_CHECK_INTERVAL == 0 && state.abandoned.load(Ordering::SeqCst)
+        {
+            return None;
+        }
+        len += 1;
+    }
+
+    Some(len)
+}
+
+// Samples quieter than this are treated as silence (about -66 dBFS).
+const SILENCE_THRESHOLD: f32 = 0.0005;
+
+// Finds the first and (one after) the last sample of a song that
+// isn't silent.  `None` if the song is silent or abandoned.
+fn find_silence<P: AsRef<Path>>(
+    path: P,
+    config: Arc<ArcConfig>,
+    state: &SongState,
+) -> Option<(usize, usize)>
+{
+    let channels = usize::from(state.channels.max(1));
+    let mut bounds = None;
+
+    for (i, sample) in SongDecoder::new(path, config).ok()?.enumerate()
+    {
+        if i % SIZE_CHECK_INTERVAL == 0 && state.abandoned.load(Ordering::SeqCst)
+        {
+            return None;
+        }
+        if sample.abs() > SILENCE_THRESHOLD
+        {
+            // Whole frames, otherwise the channels would be swapped.
+            let end = (i / channels + 1) * channels;
+            bounds = Some(bounds.map_or((i - i % channels, end), |(start, _)| (start, end)));
+        }
+    }
+
+    bounds
+}
+
+fn duration_to_samples(dur: Duration, sample_rate: u32, channels: u16) -> usize
+{
+    let frames = (dur.as_secs_f64() * sample_rate as f64).round() as usize;
+
+    frames * channels as usize
+}
+
+fn samples_to_duration(samples: usize, sample_rate: u32, channels: u16) -> Duration
+{
+    let per_sec = sample_rate as usize * channels as usize;
+
+    Duration::from_secs_f64(samples as f64 / per_sec.max(1) as f64)
 }
 
 fn get_ogg_len<P: AsRef<Path>>(path: P) -> Option<Duration>
@@ -77,100 +215,183 @@ fn get_ogg_len<P: AsRef<Path>>(path: P) -> Option<Duration>
     }
 }
 
+// Sends the decoded samples enumerated to the `ChannelSource` and
+// handles seek requests in between.  rodio's decoders can't seek, so
+// seeking forward is done by decoding and throwing away the samples
+// in between and seeking backwards by starting again from the
+// beginning.
+fn decode(
+    mut decoder: SongDecoder,
+    data_tx: &SyncSender<(usize, f32)>,
+    seek_rx: &Receiver<usize>,
+    path: &Path,
+    config: &Arc<ArcConfig>,
+)
+{
+    let channels = decoder.channels().max(1) as usize;
+    let mut i = 0;
+
+    loop
+    {
+        if let Some(target) = seek_rx.try_iter().last()
+        {
+            // Never land in the middle of a frame, otherwise the
+            // channels would be swapped.
+            let target = target - target % channels;
+
+            if target < i
+            {
+                match SongDecoder::new(path, config.clone())
+                {
+                    Ok(new_decoder) => decoder = new_decoder,
+                    Err(_) => return,
+                }
+                i = 0;
+            }
+            while i < target
+            {
+                if decoder.next().is_none()
+                {
+                    return;
+                }
+                i += 1;
+            }
+        }
+
+        match decoder.next()
+        {
+            Some(sample) if data_tx.send((i, sample)).is_ok() => i += 1,
+            _ => return,
+        }
+    }
+}
+
 impl ChannelAudio
 {
-    pub fn new<P: AsRef<Path>>(path: P, config: Arc<ArcConfig>) -> Result<Self, Error>
+    /// Opens a song for playing.  If its `duration` is already known
+    /// (from an earlier play), it's not determined again.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        duration: Option<Duration>,
+        config: Arc<ArcConfig>,
+    ) -> Result<Self, Error>
     {
         let (data_tx, data_rx) = sync_channel(64);
+        let (seek_tx, seek_rx) = channel();
 
-        let decoder = Decoder::new(Buffer::new(File::open(&path)?)?)?;
+        let decoder = SongDecoder::new(&path, config.clone())?;
         let channels = decoder.channels();
         let sample_rate = decoder.sample_rate();
 
-        config.current_len.store(0, Ordering::SeqCst);
-        config.current_pos.store(0, Ordering::SeqCst);
-        config
-            .sample_rate
-            .store(sample_rate as usize, Ordering::SeqCst);
-        config.channels.store(channels as usize, Ordering::SeqCst);
+        // Nothing of this is written into `config` yet, since this
+        // song might be prepared while another one is still playing.
+        // This happens when the `ChannelSource` plays its first
+        // sample.
+        let state = Arc::new(SongState {
+            id: config.monotonic_song_index.fetch_add(1, Ordering::SeqCst) + 1,
+            channels,
+            sample_rate,
+            len: AtomicUsize::new(0),
+            phase: AtomicU8::new(PHASE_PENDING),
+            decoded: AtomicBool::new(false),
+            abandoned: AtomicBool::new(false),
+            fade_in: AtomicUsize::new(0),
+            fade_out_start: AtomicUsize::new(usize::MAX),
+            fade_out_len: AtomicUsize::new(0),
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(usize::MAX),
+            resume: AtomicUsize::new(0),
+            silence: Mutex::new(None),
+            equalizer: Mutex::new(None),
+            equalizer_changed: AtomicBool::new(false),
+            loop_start: AtomicUsize::new(usize::MAX),
+            loop_end: AtomicUsize::new(usize::MAX),
+            loop_dropped: AtomicBool::new(false),
+            loop_seek: AtomicUsize::new(usize::MAX),
+            seeking: AtomicUsize::new(usize::MAX),
+            played: AtomicUsize::new(0),
+        });
 
+        // The duration of a song is only determined once and then
+        // stored in `songs.csv`; this is how it's done the first time.
         // To get the duration of a song, we can just decode it once
         // over and count the samples.  The advantage is that this
         // works always, the disadvantage that it takes long and is
         // inefficient, so we first try to persuade the song to tell
         // us it's length voluntarily.  This is done by using rodio's
         // `Decoder::size_hint` (works (nearly?) never, but I already
-        // implemented it) and `Decoder::total_duration` (working
-        // currently only on wavs and flacs) methods, mp3_metadata's
-        // `read_from_file` (obviously only working on mp3s) method
-        // and ogg_metadata's `read_format` (working for vorbis and
-        // opus, although rodio doesn't support decoding opus, so this
-        // is technically superfluous) method.  Without activating
-        // symphonia as rodio backend, we (should) have all cases
-        // covered, so the decoding shouldn't be necessary currently.
-        let mut size_already_safed = false;
-        if let (min, Some(max)) = decoder.size_hint()
-        {
-            if min == max && min != 0
-            {
-                config.current_len.store(min, Ordering::SeqCst);
-                size_already_safed = true;
-            }
-        }
-        else if let Some(dur) = read_from_file(&path)
-            .map(|mp3_meta| mp3_meta.duration)
-            .ok()
-            .or_else(|| decoder.total_duration())
-            .or_else(|| get_ogg_len(&path))
+        // implemented it) and `SongDecoder::total_duration` (working
+        // currently only on wavs and flacs and everything decoded by
+        // symphonia) methods, mp3_metadata's `read_from_file`
+        // (obviously only working on mp3s) method and ogg_metadata's
+        // `read_format` (working for vorbis and opus) method.  So we
+        // (should) have all cases covered and the decoding shouldn't
+        // be necessary currently.
+        let size = match decoder.size_hint()
         {
-            // `Config::current_len` is in samples not in seconds or
-            // `Duration`, so we need to convert it.
-            config.current_len.store(
-                (dur.as_secs_f64() * sample_rate as f64 * channels as f64) as usize,
-                Ordering::SeqCst,
-            );
-            size_already_safed = true;
+            (min, Some(max)) if min == max && min != 0 => Some(min),
+            _ => duration
+                .or_else(|| read_from_file(&path).map(|mp3_meta| mp3_meta.duration).ok())
+                .or_else(|| decoder.total_duration())
+                .or_else(|| get_ogg_len(&path))
+                // `SongState::len` is in samples not in seconds or
+                // `Duration`, so we need to convert it.
+                .map(|dur| duration_to_samples(dur, sample_rate, channels)),
+        };
+        if let Some(size) = size
+        {
+            state.len.store(size, Ordering::SeqCst);
         }
 
         // This decoding creates the samples which at the end actually
         // are played.
+        let path2 = path.as_ref().to_path_buf();
+        let config3 = config.clone();
+        let state2 = state.clone();
         thread::spawn(move || {
-            // Decodes all samples and sends them enumerated as long
-            // as this is possible.
-            decoder
-                .enumerate()
-                .all(|sample| data_tx.send(sample).is_ok());
+            decode(decoder, &data_tx, &seek_rx, &path2, &config3);
+            state2.decoded.store(true, Ordering::SeqCst);
         });
 
-        // Creates a new thread, checks if the size is known by now
-        // and if not decodes the complete song to get it.  It only is
-        // saved if the next song is not already played (as determined
-        // by whether the fetch_add was already once executed again).
-        let path = path.as_ref().to_path_buf();
-        let config2 = config.clone();
-        let current_index = config.monotonic_song_index.fetch_add(1, Ordering::SeqCst) + 1;
-        thread::spawn(move || {
-            if !size_already_safed
-            {
-                if let Ok(size) = get_size(path)
+        // If the size isn't known by now, a new thread decodes the
+        // complete song to get it.  It's only published if this song
+        // is the one currently playing; otherwise that happens when it
+        // starts.
+        if size.is_none()
+        {
+            let path = path.as_ref().to_path_buf();
+            let config2 = config.clone();
+            let state2 = state.clone();
+            thread::spawn(move || {
+                if let Some(size) = get_size(path, config2.clone(), &state2)
                 {
-                    if config2.monotonic_song_index.load(Ordering::SeqCst) == current_index
+                    state2.len.store(size, Ordering::SeqCst);
+                    if config2.active_song.load(Ordering::SeqCst) == state2.id
                     {
-                        config2.current_len.store(size, Ordering::SeqCst);
+                        config2
+                            .current_len
+                            .store(state2.end_pos(), Ordering::SeqCst);
                         config2.update_dbus.store(true, Ordering::SeqCst);
                     }
                 }
-            }
-        });
+            });
+        }
 
         Ok(Self {
             sample_rate,
             config: config.clone(),
+            seek_tx: Some(seek_tx),
+            state: Some(state.clone()),
             inner: Some(ChannelSource {
-                channels,
-                sample_rate,
                 data_rx,
+                state,
                 config,
+                equalizer: None,
+                loop_buffer: vec![],
+                loop_buffer_start: 0,
+                recording_from: usize::MAX,
+                looped: None,
+                held: None,
             }),
         })
     }
@@ -180,6 +401,39 @@ impl ChannelAudio
         self.config.current_pos.load(Ordering::SeqCst)
     }
 
+    /// Jumps to the `pos`th sample (counted over all channels like
+    /// `ArcConfig::current_pos`).  Returns whether there was something
+    /// to seek in.
+    pub fn seek(&self, pos: usize) -> bool
+    {
+        // Before sending, since the decoder might be faster.
+        if let Some(state) = &self.state
+        {
+            state.seeking.store(pos, Ordering::SeqCst);
+        }
+        let sent = self
+            .seek_tx
+            .as_ref()
+            .is_some_and(|seek_tx| seek_tx.send(pos).is_ok());
+
+        if sent
+        {
+            self.config.current_pos.store(pos, Ordering::SeqCst);
+            self.config.seeked.store(true, Ordering::SeqCst);
+            if let Some(state) = &self.state
+            {
+                state.resume.store(0, Ordering::SeqCst);
+                state.loop_dropped.store(true, Ordering::SeqCst);
+            }
+        }
+        else if let Some(state) = &self.state
+        {
+            state.seeking.store(usize::MAX, Ordering::SeqCst);
+        }
+
+        sent
+    }
+
     pub fn samples_len(&mut self) -> Option<usize>
     {
         let len = self.config.current_len.load(Ordering::SeqCst);
@@ -193,23 +447,546 @@ impl ChannelAudio
             Some(len)
         }
     }
+
+    /// Whether the first sample of this song was already played.
+    pub fn started(&self) -> bool
+    {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.phase.load(Ordering::SeqCst) == PHASE_STARTED)
+    }
+
+    /// The duration of this song if it's known (yet).
+    pub fn duration(&self) -> Option<Duration>
+    {
+        self.state.as_ref().and_then(|state| {
+            let len = state.len.load(Ordering::SeqCst);
+            let per_sec = state.sample_rate as usize * state.channels as usize;
+
+            (len != 0 && per_sec != 0).then(|| Duration::from_secs_f64(len as f64 / per_sec as f64))
+        })
+    }
+
+    /// How long this song was actually played so far.
+    pub fn played(&self) -> Option<Duration>
+    {
+        self.state.as_ref().map(|state| {
+            samples_to_duration(
+                state.played.load(Ordering::SeqCst),
+                state.sample_rate,
+                state.channels,
+            )
+        })
+    }
+
+    /// Whether the decoder already reached the end of this song.
+    pub fn decoded(&self) -> bool
+    {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.decoded.load(Ordering::SeqCst))
+    }
+
+    /// Converts milliseconds into samples of this song.
+    pub fn ms_to_samples(&self, ms: u64) -> usize
+    {
+        self.state.as_ref().map_or(0, |state| {
+            (ms as u128 * state.sample_rate as u128 * state.channels as u128 / 1000) as usize
+        })
+    }
+
+    /// Lets the song start silently and get to full volume in the
+    /// first `len` samples.
+    pub fn fade_in(&self, len: usize)
+    {
+        if let Some(state) = &self.state
+        {
+            state.fade_in.store(len, Ordering::SeqCst);
+        }
+    }
+
+    /// Lets the song get silent over `len` samples starting from the
+    /// `from`th sample and end then.
+    pub fn fade_out(&self, from: usize, len: usize)
+    {
+        if let Some(state) = &self.state
+        {
+            // Length first, so that the song doesn't end immediately
+            // when it's read in between.
+            state.fade_out_len.store(len, Ordering::SeqCst);
+            state.fade_out_start.store(from, Ordering::SeqCst);
+        }
+    }
+
+    /// Plays the song from now on with `preset` (or unfiltered if
+    /// it's `None`).
+    pub fn set_equalizer(&self, preset: Option<Arc<Preset>>)
+    {
+        if let Some(state) = &self.state
+        {
+            if let Ok(mut equalizer) = state.equalizer.lock()
+            {
+                let same = match (&*equalizer, &preset)
+                {
+                    (Some(old), Some(new)) => Arc::ptr_eq(old, new),
+                    (old, new) => old.is_none() && new.is_none(),
+                };
+
+                // Otherwise the filters would be reset for nothing.
+                if !same
+                {
+                    *equalizer = preset;
+                    state.equalizer_changed.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+
+    /// Plays only the part of the song between `start` and `end`
+    /// (the beginning and the end of the song if they are `None`).
+    pub fn set_trim(&self, start: Option<Duration>, end: Option<Duration>)
+    {
+        let Some(state) = &self.state
+        else
+        {
+            return;
+        };
+        let to_samples = |dur| duration_to_samples(dur, state.sample_rate, state.channels);
+        let start = start.map_or(0, to_samples);
+
+        state
+            .end
+            .store(end.map_or(usize::MAX, to_samples), Ordering::SeqCst);
+        let old_start = state.start.swap(start, Ordering::SeqCst);
+
+        let active = self.config.active_song.load(Ordering::SeqCst) == state.id;
+        if active
+        {
+            self.config
+                .current_len
+                .store(state.end_pos(), Ordering::SeqCst);
+            self.config.update_dbus.store(true, Ordering::SeqCst);
+        }
+
+        // The `ChannelSource` skips everything before the start
+        // anyway, but it's faster if the decoder doesn't have to send
+        // it.
+        let before_start = state.phase.load(Ordering::SeqCst) == PHASE_PENDING
+            || (active && self.config.current_pos.load(Ordering::SeqCst) < start);
+        if start != old_start && before_start
+        {
+            if let Some(seek_tx) = &self.seek_tx
+            {
+                let _ = seek_tx.send(start);
+            }
+        }
+    }
+
+    /// Lets the song begin at `pos` this time instead of at its start,
+    /// so that it goes on where it was stopped the last time.
+    pub fn resume_at(&self, pos: Duration)
+    {
+        let Some(state) = &self.state
+        else
+        {
+            return;
+        };
+        let pos = duration_to_samples(pos, state.sample_rate, state.channels);
+
+        state.resume.store(pos, Ordering::SeqCst);
+        if let Some(seek_tx) = &self.seek_tx
+        {
+            let _ = seek_tx.send(pos);
+        }
+    }
+
+    /// Determines in the background where the song's leading and
+    /// trailing silence is; the result can be gotten with
+    /// `take_silence`.
+    pub fn detect_silence<P: AsRef<Path>>(&self, path: P)
+    {
+        let Some(state) = self.state.clone()
+        else
+        {
+            return;
+        };
+        let path = path.as_ref().to_path_buf();
+        let config = self.config.clone();
+
+        thread::spawn(move || {
+            if let Some(bounds) = find_silence(path, config, &state)
+            {
+                if let Ok(mut silence) = state.silence.lock()
+                {
+                    *silence = Some(bounds);
+                }
+            }
+        });
+    }
+
+    /// The part of the song that isn't silent, if it was determined
+    /// by now.  Returns it only once.
+    pub fn take_silence(&self) -> Option<(Duration, Duration)>
+    {
+        let state = self.state.as_ref()?;
+        let (start, end) = state.silence.lock().ok()?.take()?;
+
+        Some((
+            samples_to_duration(start, state.sample_rate, state.channels),
+            samples_to_duration(end, state.sample_rate, state.channels),
+        ))
+    }
+
+    /// Converts a position in samples (like `ArcConfig::current_pos`)
+    /// of this song into a `Duration`.
+    pub fn pos_to_duration(&self, pos: usize) -> Option<Duration>
+    {
+        self.state
+            .as_ref()
+            .map(|state| samples_to_duration(pos, state.sample_rate, state.channels))
+    }
+
+    /// Starts remembering the song from the `pos`th sample on, so
+    /// that the part from there can be looped.  A running loop is
+    /// stopped and the new one starts at its end, since that's where
+    /// the song goes on.  Returns where it actually starts.
+    pub fn set_loop_start(&self, pos: usize) -> Option<usize>
+    {
+        let state = self.state.as_ref()?;
+        let end = state.loop_end.swap(usize::MAX, Ordering::SeqCst);
+        let pos = if end == usize::MAX { pos } else { end };
+        state.loop_start.store(pos, Ordering::SeqCst);
+
+        Some(pos)
+    }
+
+    /// Where the loop starts (set with `set_loop_start`).
+    pub fn loop_start(&self) -> Option<usize>
+    {
+        self.state
+            .as_ref()
+            .map(|state| state.loop_start.load(Ordering::SeqCst))
+            .filter(|&start| start != usize::MAX)
+    }
+
+    /// Loops the part of the song between `loop_start` and the `pos`th
+    /// sample, which must be at most `MAX_LOOP` after it.
+    pub fn set_loop_end(&self, pos: usize)
+    {
+        if let Some(state) = &self.state
+        {
+            state.loop_end.store(pos, Ordering::SeqCst);
+        }
+    }
+
+    /// Goes to the `pos`th sample if it's inside the loop, which is
+    /// played from memory then and keeps going on.  Returns `false` if
+    /// it isn't.
+    pub fn seek_in_loop(&self, pos: usize) -> bool
+    {
+        let Some(state) = &self.state
+        else
+        {
+            return false;
+        };
+        let start = state.loop_start.load(Ordering::SeqCst);
+        let end = state.loop_end.load(Ordering::SeqCst);
+        if end == usize::MAX || pos < start || pos >= end
+        {
+            return false;
+        }
+
+        state.loop_seek.store(pos, Ordering::SeqCst);
+        self.config.current_pos.store(pos, Ordering::SeqCst);
+        self.config.seeked.store(true, Ordering::SeqCst);
+
+        true
+    }
+
+    /// Stops looping and forgets the start of the loop; the song goes
+    /// on after the end of the loop.
+    pub fn clear_loop(&self)
+    {
+        if let Some(state) = &self.state
+        {
+            state.loop_end.store(usize::MAX, Ordering::SeqCst);
+            state.loop_start.store(usize::MAX, Ordering::SeqCst);
+        }
+    }
+
+    /// Makes the `ChannelSource` end before it even started, so that
+    /// it can be left in the `Sink` without being heard.  Returns
+    /// `false` if it's too late for that.
+    pub fn cancel(&self) -> bool
+    {
+        self.state.as_ref().is_some_and(|state| {
+            state
+                .phase
+                .compare_exchange(
+                    PHASE_PENDING,
+                    PHASE_CANCELLED,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+        })
+    }
+}
+
+impl Drop for ChannelAudio
+{
+    fn drop(&mut self)
+    {
+        if let Some(state) = &self.state
+        {
+            state.abandoned.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl SongState
+{
+    // The factor the `i`th sample is multiplied with because of fading
+    // or `None` if the song already faded out completely.
+    fn gain(&self, i: usize) -> Option<f32>
+    {
+        let mut gain = 1.0;
+
+        // Fading in starts wherever the song starts.
+        let fade_in = self.fade_in.load(Ordering::SeqCst);
+        let since_start = i.saturating_sub(self.start.load(Ordering::SeqCst));
+        if since_start < fade_in
+        {
+            gain *= since_start as f32 / fade_in as f32;
+        }
+
+        let fade_out_start = self.fade_out_start.load(Ordering::SeqCst);
+        if i >= fade_out_start
+        {
+            let len = self.fade_out_len.load(Ordering::SeqCst);
+            let done = i - fade_out_start;
+            if done >= len
+            {
+                return None;
+            }
+            gain *= 1.0 - done as f32 / len as f32;
+        }
+
+        Some(gain)
+    }
+
+    // Where the song ends (in samples) or zero if it's unknown.
+    fn end_pos(&self) -> usize
+    {
+        let len = self.len.load(Ordering::SeqCst);
+        let end = self.end.load(Ordering::SeqCst);
+
+        match (len, end)
+        {
+            (_, usize::MAX) => len,
+            (0, end) => end,
+            (len, end) => len.min(end),
+        }
+    }
+}
+
+impl ChannelSource
+{
+    // Publishes this song as the one currently playing.
+    fn start(&self)
+    {
+        let state = &self.state;
+
+        self.config.current_pos.store(0, Ordering::SeqCst);
+        self.config
+            .current_len
+            .store(state.end_pos(), Ordering::SeqCst);
+        self.config
+            .sample_rate
+            .store(state.sample_rate as usize, Ordering::SeqCst);
+        self.config
+            .channels
+            .store(state.channels as usize, Ordering::SeqCst);
+        self.config.active_song.store(state.id, Ordering::SeqCst);
+        self.config.update_dbus.store(true, Ordering::SeqCst);
+    }
+
+    // The next sample from the decoder or from the loop buffer while
+    // looping; everything before the start is skipped.
+    fn next_sample(&mut self) -> Option<(usize, f32)>
+    {
+        let state = &self.state;
+        let channels = usize::from(state.channels.max(1));
+        let loop_start = state.loop_start.load(Ordering::SeqCst);
+        let loop_end = state.loop_end.load(Ordering::SeqCst);
+
+        if state.loop_dropped.swap(false, Ordering::SeqCst)
+        {
+            self.looped = None;
+            self.held = None;
+        }
+        let target = state.loop_seek.swap(usize::MAX, Ordering::SeqCst);
+        if target != usize::MAX && loop_start == self.recording_from
+        {
+            // Before the recording started it goes to the start of the
+            // loop.
+            let k = target.saturating_sub(self.loop_buffer_start);
+            let k = k - k % channels;
+            if k < self.loop_buffer.len()
+            {
+                self.looped = Some(k);
+            }
+        }
+        if let Some(k) = self.looped
+        {
+            // When the loop is stopped, it's still played to its end,
+            // so that the song goes on without a jump.
+            let looping = loop_end != usize::MAX && loop_start == self.recording_from;
+            let len = if looping
+            {
+                let len = self
+                    .loop_buffer
+                    .len()
+                    .min(loop_end.saturating_sub(self.loop_buffer_start));
+                len - len % channels
+            }
+            else
+            {
+                self.loop_buffer.len()
+            };
+            // Only after the end was reached the first time the loop
+            // is complete; otherwise the decoder goes on where the
+            // recording stopped.
+            let k = if looping && k >= len && self.held.is_some()
+            {
+                0
+            }
+            else
+            {
+                k
+            };
+
+            if k < len
+            {
+                self.looped = Some(k + 1);
+                return Some((self.loop_buffer_start + k, self.loop_buffer[k]));
+            }
+            self.looped = None;
+        }
+
+        if loop_start != self.recording_from
+        {
+            self.loop_buffer.clear();
+            self.recording_from = loop_start;
+        }
+
+        let (i, val) = match self.held.take()
+        {
+            Some(sample) => sample,
+            None => loop
+            {
+                let (i, val) = self.data_rx.recv().ok()?;
+                let seeking = state.seeking.load(Ordering::SeqCst);
+                if seeking != usize::MAX
+                {
+                    // `decode` lands on the first sample of a frame.
+                    if i != seeking - seeking % channels
+                    {
+                        continue;
+                    }
+                    state.seeking.store(usize::MAX, Ordering::SeqCst);
+                }
+                if i >= state.start.load(Ordering::SeqCst)
+                    && i >= state.resume.load(Ordering::SeqCst)
+                {
+                    state.resume.store(0, Ordering::SeqCst);
+                    break (i, val);
+                }
+            },
+        };
+
+        // Loops only start and end with whole frames, otherwise the
+        // channels would be swapped.
+        if i >= loop_end && i % channels == 0 && !self.loop_buffer.is_empty()
+        {
+            self.held = Some((i, val));
+            self.looped = Some(1);
+            return Some((self.loop_buffer_start, self.loop_buffer[0]));
+        }
+        if self.loop_buffer.len() < duration_to_samples(MAX_LOOP, state.sample_rate, state.channels)
+        {
+            // After a jump the recording has to start anew.
+            if i != self.loop_buffer_start + self.loop_buffer.len()
+            {
+                self.loop_buffer.clear();
+            }
+            if i >= loop_start && (!self.loop_buffer.is_empty() || i % channels == 0)
+            {
+                if self.loop_buffer.is_empty()
+                {
+                    self.loop_buffer_start = i;
+                }
+                self.loop_buffer.push(val);
+            }
+        }
+
+        Some((i, val))
+    }
+
+    // Creates new filters for the preset of the song.
+    fn update_equalizer(&mut self)
+    {
+        let state = &self.state;
+
+        if let Ok(preset) = state.equalizer.lock()
+        {
+            self.equalizer = preset
+                .as_ref()
+                .and_then(|preset| Equalizer::new(preset, state.sample_rate, state.channels));
+        }
+    }
 }
 
 impl Iterator for ChannelSource
 {
-    type Item = i16;
+    type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item>
     {
-        if let Ok((i, val)) = self.data_rx.recv()
+        match self.state.phase.compare_exchange(
+            PHASE_PENDING,
+            PHASE_STARTED,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
         {
-            self.config.current_pos.store(i, Ordering::SeqCst);
-            Some(val)
+            Ok(_) => self.start(),
+            Err(PHASE_CANCELLED) => return None,
+            Err(_) =>
+            {}
         }
-        else
+
+        let (i, mut val) = self.next_sample()?;
+        if i >= self.state.end.load(Ordering::SeqCst)
         {
-            None
+            return None;
         }
+        let gain = self.state.gain(i)?;
+
+        self.config.current_pos.store(i, Ordering::SeqCst);
+        self.config.samples_played.fetch_add(1, Ordering::SeqCst);
+        self.state.played.fetch_add(1, Ordering::SeqCst);
+
+        if self.state.equalizer_changed.swap(false, Ordering::SeqCst)
+        {
+            self.update_equalizer();
+        }
+        if let Some(equalizer) = &mut self.equalizer
+        {
+            val = equalizer.process(i, val);
+        }
+
+        Some(val * gain)
     }
 }
 
@@ -222,12 +999,12 @@ impl Source for ChannelSource
 
     fn channels(&self) -> u16
     {
-        self.channels
+        self.state.channels
     }
 
     fn sample_rate(&self) -> u32
     {
-        self.sample_rate
+        self.state.sample_rate
     }
 
     fn total_duration(&self) -> Option<Duration>
@@ -236,13 +1013,12 @@ impl Source for ChannelSource
     }
 }
 
-fn print_tag(tag: &Tag, l10n: L10n)
+fn print_metadata(metadata: &Metadata, l10n: L10n)
 {
     macro_rules! simple {
-        ( $l10n : tt , $method : tt , $name : tt ) => {
-            if let Some(v) = tag.$method()
+        ( $l10n : tt , $field : tt , $name : tt ) => {
+            if let Some(v) = &metadata.$field
             {
-                //println!("{}: {}", $l10n.get($name, vec![]), v);
                 $l10n.write(Message::$name(v.to_string()));
             }
         };
@@ -262,46 +1038,49 @@ fn print_tag(tag: &Tag, l10n: L10n)
     simple!(l10n, total_discs, DiscsTotal);
     simple!(l10n, track, Track);
     simple!(l10n, total_tracks, TracksTotal);
-    simple!(l10n, duration, Duration);
+    if let Some(duration) = metadata.duration
+    {
+        l10n.write(Message::Duration(format!("{:.1}s", duration.as_secs_f64())));
+    }
 
-    for v in tag.lyrics()
+    for v in &metadata.lyrics
     {
-        l10n.write(Message::Lyrics(v));
+        l10n.write(Message::Lyrics(v.clone()));
     }
-    for v in tag.synchronised_lyrics()
+    if !metadata.sync_lyrics.is_empty()
     {
-        l10n.write(Message::SyncLyrics(format!("{:?}", v)));
+        l10n.write(Message::SyncLyrics(format_lyrics(&metadata.sync_lyrics)));
     }
-    for v in tag.comments()
+    for v in &metadata.comments
     {
-        l10n.write(Message::Comment(v));
+        l10n.write(Message::Comment(v.clone()));
     }
 
-    l10n.write(Message::NumPictures(tag.pictures().count()));
+    l10n.write(Message::NumPictures(metadata.pictures.len()));
 
-    if tag.extended_links().next().is_some()
+    if !metadata.links.is_empty()
     {
         l10n.write(Message::ExtLinks);
-        for v in tag.extended_links()
+        for (description, link) in &metadata.links
         {
-            println!("{}", v);
+            l10n.write(Message::ExtLink(description.clone(), link.clone()));
         }
     }
-    if tag.extended_texts().next().is_some()
+    if !metadata.texts.is_empty()
     {
         l10n.write(Message::ExtTexts);
-        for v in tag.extended_texts()
+        for (description, text) in &metadata.texts
         {
-            println!("{}", v);
+            l10n.write(Message::ExtText(description.clone(), text.clone()));
         }
     }
 }
 
-pub fn print_info(tag: &Option<Result<Tag, id3::Error>>, l10n: L10n)
+pub fn print_info(metadata: &Option<Result<Metadata, Error>>, l10n: L10n)
 {
-    match tag
+    match metadata
     {
-        Some(Ok(tag)) => print_tag(tag, l10n),
+        Some(Ok(metadata)) => print_metadata(metadata, l10n),
         Some(Err(err)) =>
         {
             l10n.write(Message::MetadataUnsupported(err.to_string()));
@@ -1,75 +1,418 @@
 use std::{
     cmp::min,
-    io::{self, Read, Seek},
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{atomic::Ordering, Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::Duration,
 };
 
-use crate::Error;
+use crate::{config::ArcConfig, Error};
 
+// How much is read from the disk at once by the read-ahead thread.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+struct Window
+{
+    // The buffered part of the file; `data[0]` is the byte at offset
+    // `start` in the file.
+    data: VecDeque<u8>,
+    start: u64,
+    // Where the decoder currently reads.
+    pos: u64,
+    len: u64,
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+struct Shared
+{
+    window: Mutex<Window>,
+    cond: Condvar,
+}
+
+/// A bounded streaming reader over a song file.
+///
+/// A background thread keeps at most `read_ahead` bytes in front of
+/// the decoder in memory (and the same amount behind it, so that the
+/// probing of rodio's decoders – which seek back to the start a
+/// couple of times – doesn't have to go to the disk again).  While
+/// reading is paused by `SIGUSR1` the thread doesn't touch the disk
+/// at all and the decoder is only served what was read ahead; if that
+/// runs out it waits until `SIGUSR2`.
 pub struct Buffer
 {
-    buf: Vec<u8>,
-    pos: usize,
+    shared: Arc<Shared>,
+    read_ahead: u64,
 }
 
-impl Read for Buffer
+impl Window
 {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error>
+    fn end(&self) -> u64
     {
-        if self.pos >= self.buf.len()
+        self.start + self.data.len() as u64
+    }
+
+    // Forgets everything that is more than `keep` bytes behind the
+    // current position.
+    fn trim(&mut self, keep: u64)
+    {
+        if self.pos > self.start + keep
         {
-            // TODO: Return correct error
-            return Ok(0);
+            let drop = min(self.pos - self.start - keep, self.data.len() as u64);
+            self.data.drain(..drop as usize);
+            self.start += drop;
         }
-        let slice = &self.buf[self.pos..];
-        let slice = &slice[..min(buf.len(), slice.len())];
-        let len = min(slice.len(), buf.len());
+    }
+}
+
+impl Shared
+{
+    fn lock(&self) -> MutexGuard<'_, Window>
+    {
+        self.window
+            .lock()
+            .expect("Lock over read-ahead buffer is poisoned")
+    }
+
+    fn wait<'a>(&self, guard: MutexGuard<'a, Window>) -> MutexGuard<'a, Window>
+    {
+        // The timeout is needed since `reading_paused` is an atomic
+        // and its change doesn't notify us.
+        self.cond
+            .wait_timeout(guard, Duration::from_millis(10))
+            .expect("Lock over read-ahead buffer is poisoned")
+            .0
+    }
+}
+
+// `paused` tells whether the disk mustn't be touched right now.
+fn read_ahead<R, F>(mut file: R, shared: &Shared, paused: F, read_ahead: u64)
+where
+    R: Read + Seek,
+    F: Fn() -> bool,
+{
+    let mut file_pos = 0;
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop
+    {
+        let offset = {
+            let mut window = shared.lock();
+            loop
+            {
+                if window.closed
+                {
+                    return;
+                }
+                let end = window.end();
+                if end < window.len && end < window.pos + read_ahead && !paused()
+                {
+                    break end;
+                }
+                window = shared.wait(window);
+            }
+        };
 
-        (buf[..len]).copy_from_slice(&slice[..len]);
+        if file_pos != offset
+        {
+            if let Err(e) = file.seek(SeekFrom::Start(offset))
+            {
+                shared.lock().error = Some(e);
+                shared.cond.notify_all();
+                return;
+            }
+        }
 
-        self.pos += len;
+        let result = file.read(&mut chunk);
 
-        Ok(len)
+        let mut window = shared.lock();
+        match result
+        {
+            Ok(0) =>
+            {
+                // The file got shorter since we opened it; treat the
+                // current end as the real one.
+                window.len = offset;
+                file_pos = offset;
+            }
+            Ok(n) =>
+            {
+                file_pos = offset + n as u64;
+                // The decoder could have seeked somewhere else in the
+                // meantime, in which case this chunk is useless.
+                if window.end() == offset
+                {
+                    window.data.extend(&chunk[..n]);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted =>
+            {}
+            Err(e) =>
+            {
+                window.error = Some(e);
+                shared.cond.notify_all();
+                return;
+            }
+        }
+        drop(window);
+        shared.cond.notify_all();
     }
 }
 
-impl Seek for Buffer
+impl Read for Buffer
 {
-    fn seek(&mut self, pos: io::SeekFrom) -> Result<u64, io::Error>
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error>
     {
-        match pos
+        let mut window = self.shared.lock();
+
+        loop
         {
-            io::SeekFrom::Current(x) => self.pos += x as usize,
-            io::SeekFrom::Start(x) => self.pos = x as usize,
-            io::SeekFrom::End(x) =>
+            if window.pos >= window.len || buf.is_empty()
+            {
+                return Ok(0);
+            }
+
+            if window.pos < window.start || window.pos > window.end()
             {
-                let x = x as usize;
-                if self.buf.len() + x == 0
+                // Seeked out of the buffered part, so start anew at
+                // the requested position.
+                window.data.clear();
+                window.start = window.pos;
+                self.shared.cond.notify_all();
+            }
+
+            if window.pos < window.end()
+            {
+                let offset = (window.pos - window.start) as usize;
+                let (a, b) = window.data.as_slices();
+                let slice = if offset < a.len()
                 {
-                    // TODO: Return `io::Error` with `ErrorKind` being
-                    // `InvalidInput`.
-                    self.pos = 0;
+                    &a[offset..]
                 }
                 else
                 {
-                    self.pos = self.buf.len() + x - 1;
-                }
+                    &b[offset - a.len()..]
+                };
+                let len = min(slice.len(), buf.len());
+
+                buf[..len].copy_from_slice(&slice[..len]);
+
+                window.pos += len as u64;
+                window.trim(self.read_ahead);
+                drop(window);
+                self.shared.cond.notify_all();
+
+                return Ok(len);
+            }
+
+            if let Some(e) = window.error.take()
+            {
+                return Err(e);
             }
+
+            window = self.shared.wait(window);
+        }
+    }
+}
+
+impl Seek for Buffer
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error>
+    {
+        let mut window = self.shared.lock();
+
+        let new_pos = match pos
+        {
+            SeekFrom::Start(x) => Some(x),
+            SeekFrom::Current(x) => window.pos.checked_add_signed(x),
+            SeekFrom::End(x) => window.len.checked_add_signed(x),
         }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        window.pos = new_pos;
+        drop(window);
 
-        Ok(self.pos as _)
+        Ok(new_pos)
+    }
+}
+
+#[cfg(feature = "symphonia")]
+impl symphonia::core::io::MediaSource for Buffer
+{
+    fn is_seekable(&self) -> bool
+    {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64>
+    {
+        Some(self.shared.lock().len)
+    }
+}
+
+impl Drop for Buffer
+{
+    fn drop(&mut self)
+    {
+        self.shared.lock().closed = true;
+        self.shared.cond.notify_all();
     }
 }
 
 impl Buffer
 {
-    pub fn new<R: Read>(mut reader: R) -> Result<Self, Error>
+    pub fn new<P: AsRef<Path>>(path: P, config: Arc<ArcConfig>) -> Result<Self, Error>
     {
-        let mut buf = Self {
-            buf: vec![],
-            pos: 0,
-        };
-        reader.read_to_end(&mut buf.buf)?;
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        // With less than a chunk nothing would ever be read.
+        let read_ahead_len = config.conffile.read_ahead.max(CHUNK_SIZE as u64);
+
+        Ok(Self::with_reader(file, len, read_ahead_len, move || {
+            config.reading_paused.load(Ordering::SeqCst)
+        }))
+    }
+
+    // The same for anything that can be read, which is `len` bytes
+    // long.
+    fn with_reader<R, F>(reader: R, len: u64, read_ahead_len: u64, paused: F) -> Self
+    where
+        R: Read + Seek + Send + 'static,
+        F: Fn() -> bool + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            window: Mutex::new(Window {
+                data: VecDeque::new(),
+                start: 0,
+                pos: 0,
+                len,
+                error: None,
+                closed: false,
+            }),
+            cond: Condvar::new(),
+        });
+
+        let shared2 = shared.clone();
+        thread::spawn(move || read_ahead(reader, &shared2, paused, read_ahead_len));
+
+        Self {
+            shared,
+            read_ahead: read_ahead_len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::Cursor;
+
+    use super::*;
+
+    // Some chunks and a bit, so that reading has to wait for the
+    // read-ahead thread a couple of times.
+    const LEN: usize = 5 * CHUNK_SIZE + 123;
+
+    fn data() -> Vec<u8>
+    {
+        (0..LEN).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn buffer() -> Buffer
+    {
+        Buffer::with_reader(Cursor::new(data()), LEN as u64, CHUNK_SIZE as u64, || false)
+    }
+
+    #[test]
+    fn reads_everything()
+    {
+        let mut buffer = buffer();
+        let mut read: Vec<u8> = vec![];
+        // Odd sizes, so that reads go across the ends of chunks.
+        let mut buf = vec![0; 1000];
+        loop
+        {
+            let len = buffer.read(&mut buf).unwrap();
+            if len == 0
+            {
+                break;
+            }
+            read.extend(&buf[..len]);
+        }
+
+        assert_eq!(read, data());
+        assert_eq!(buffer.stream_position().unwrap(), LEN as u64);
+    }
+
+    #[test]
+    fn seeks_inside_window()
+    {
+        let mut buffer = buffer();
+        let mut buf = vec![0; 5000];
+        buffer.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data()[..5000]);
+        // Still in memory, since it's less than `read_ahead` behind.
+        assert_eq!(buffer.shared.lock().start, 0);
+
+        assert_eq!(buffer.seek(SeekFrom::Start(10)).unwrap(), 10);
+        buffer.read_exact(&mut buf[..100]).unwrap();
+        assert_eq!(buf[..100], data()[10..110]);
+
+        assert_eq!(buffer.seek(SeekFrom::Current(-50)).unwrap(), 60);
+        buffer.read_exact(&mut buf[..100]).unwrap();
+        assert_eq!(buf[..100], data()[60..160]);
+        assert_eq!(buffer.stream_position().unwrap(), 160);
+    }
+
+    #[test]
+    fn seeks_back_out_of_window()
+    {
+        let mut buffer = buffer();
+        let mut buf = vec![0; 3 * CHUNK_SIZE];
+        buffer.read_exact(&mut buf).unwrap();
+        // The start was forgotten.
+        assert!(buffer.shared.lock().start > 100);
+
+        assert_eq!(buffer.seek(SeekFrom::Start(100)).unwrap(), 100);
+        buffer.read_exact(&mut buf[..CHUNK_SIZE]).unwrap();
+        assert_eq!(buf[..CHUNK_SIZE], data()[100..100 + CHUNK_SIZE]);
+        assert_eq!(buffer.stream_position().unwrap(), 100 + CHUNK_SIZE as u64);
+    }
+
+    #[test]
+    fn seeks_forward_out_of_window()
+    {
+        let mut buffer = buffer();
+        let mut buf = vec![0; 200];
+        buffer.read_exact(&mut buf).unwrap();
+
+        assert_eq!(buffer.seek(SeekFrom::End(-100)).unwrap(), LEN as u64 - 100);
+        let mut rest = vec![];
+        buffer.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, data()[LEN - 100..]);
+        assert_eq!(buffer.read(&mut buf).unwrap(), 0);
+
+        // Back to the start of the file once more.
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        buffer.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data()[..200]);
+    }
+
+    #[test]
+    fn rejects_negative_seeks()
+    {
+        let mut buffer = buffer();
 
-        Ok(buf)
+        assert!(buffer.seek(SeekFrom::Current(-1)).is_err());
+        assert!(buffer.seek(SeekFrom::End(-(LEN as i64) - 1)).is_err());
+        assert_eq!(buffer.stream_position().unwrap(), 0);
     }
 }
@@ -1,9 +1,17 @@
-use std::{process, sync::atomic::Ordering, thread};
+use std::{mem, path::PathBuf, process, sync::atomic::Ordering, thread, time::Duration};
 
 use rodio::Sink;
 
 use crate::{
-    audio::print_info, config::Config, l10n::messages::Message, matcher::BigAction, songs::Repeat,
+    art::cover,
+    audio::{print_info, MAX_LOOP},
+    config::{Config, MAX_RATE, MIN_RATE},
+    equalizer::{find_preset, FLAT},
+    hooks::Event,
+    l10n::messages::Message,
+    matcher::BigAction,
+    sleep::SleepTimer,
+    songs::Repeat,
 };
 
 use super::Command;
@@ -17,7 +25,9 @@ fn increase_likelihood(config: &mut Config) -> BigAction
     else
     {
         config.num += 1;
+        config.settings_changed = true;
         config.l10n.write(Message::LikelihoodIncreased(config.num));
+        config.run_hooks(Event::LikelihoodChanged);
     }
 
     BigAction::Nothing
@@ -32,7 +42,9 @@ fn decrease_likelihood(config: &mut Config) -> BigAction
     else
     {
         config.num -= 1;
+        config.settings_changed = true;
         config.l10n.write(Message::LikelihoodDecreased(config.num));
+        config.run_hooks(Event::LikelihoodChanged);
 
         if config.num == 0
         {
@@ -60,6 +72,8 @@ fn pause(config: &mut Config) -> BigAction
         config.l10n.write(Message::Pausing);
         config.paused = true;
         config.sink.pause();
+        config.fading.iter().for_each(Sink::pause);
+        config.run_hooks(Event::Paused);
     }
     config.arc_config.update_dbus.store(true, Ordering::SeqCst);
 
@@ -73,6 +87,8 @@ fn resume(config: &mut Config) -> BigAction
         config.l10n.write(Message::Resuming);
         config.paused = false;
         config.sink.play();
+        config.fading.iter().for_each(Sink::play);
+        config.run_hooks(Event::Resumed);
     }
     else
     {
@@ -85,9 +101,43 @@ fn resume(config: &mut Config) -> BigAction
 
 fn skip(config: &mut Config) -> BigAction
 {
+    stop_loop(config);
     config.l10n.write(Message::SkippingSong);
+    config.run_hooks(Event::Skipped);
+    config.skipped = true;
+    // A next song which is already queued behind the current one would
+    // be played after the fade out, but it starts right away anyway.
+    if config.next.as_ref().is_some_and(|next| next.queued)
+    {
+        if let Some(next) = config.next.take()
+        {
+            next.audio.cancel();
+        }
+    }
+
+    let pos = config.source.get_pos();
+    if let Some(sink) = config.new_sink()
+    {
+        let sink = mem::replace(&mut config.sink, sink);
+        // A paused song would never finish fading out.
+        if !config.paused
+        {
+            config.source.fade_out(
+                pos,
+                config
+                    .source
+                    .ms_to_samples(config.arc_config.conffile.skip_fade_ms),
+            );
+            config.fading.push(sink);
+        }
+    }
+    else
+    {
+        // Without another sink it has to end in the current one.
+        config.source.fade_out(pos, 0);
+        config.sink.play();
+    }
     config.paused = false;
-    config.sink = Sink::try_new(&config.stream_handle).unwrap();
     config.arc_config.update_dbus.store(true, Ordering::SeqCst);
 
     BigAction::Nothing
@@ -96,8 +146,10 @@ fn skip(config: &mut Config) -> BigAction
 fn increase_volume(config: &mut Config) -> BigAction
 {
     config.loud += 0.01;
+    config.settings_changed = true;
     config.l10n.write(Message::MakingLouder(config.loud as f64));
-    config.sink.set_volume(config.loud);
+    config.apply_volume();
+    config.run_hooks(Event::VolumeChanged);
 
     BigAction::Nothing
 }
@@ -105,6 +157,7 @@ fn increase_volume(config: &mut Config) -> BigAction
 fn decrease_volume(config: &mut Config) -> BigAction
 {
     config.loud -= 0.01;
+    config.settings_changed = true;
     config
         .l10n
         .write(Message::MakingQuieter(config.loud as f64));
@@ -113,27 +166,69 @@ fn decrease_volume(config: &mut Config) -> BigAction
         config.l10n.write(Message::LoudZero);
         config.loud = 0.0;
     }
-    config.sink.set_volume(config.loud);
+    config.apply_volume();
+    config.run_hooks(Event::VolumeChanged);
 
     BigAction::Nothing
 }
 
+fn show_sleep_timer(config: &Config, timer: SleepTimer)
+{
+    let left = timer.left().as_secs_f64();
+
+    config.l10n.write(
+        if timer.pause
+        {
+            Message::PausingIn(left)
+        }
+        else
+        {
+            Message::QuittingIn(left)
+        },
+    );
+}
+
 fn show_duration(config: &mut Config) -> BigAction
 {
     let pos = config.source.get_pos() as f64
         / config.source.sample_rate as f64
         / config.arc_config.channels.load(Ordering::SeqCst) as f64;
 
+    // The position and length are in the time of the song; when it's
+    // played faster or slower, the time that's actually left is
+    // different.
+    let rate = config.effective_rate() as f64;
+
     if let Some(len) = config.source.samples_len()
     {
         let len = len as f64
             / config.source.sample_rate as f64
             / config.arc_config.channels.load(Ordering::SeqCst) as f64;
         config.l10n.write(Message::DurationKnown(pos, len));
+        if (rate - 1.0).abs() > f64::EPSILON
+        {
+            config
+                .l10n
+                .write(Message::RealTimeLeft(rate, (len - pos).max(0.0) / rate));
+        }
     }
     else
     {
         config.l10n.write(Message::DurationUnknown(pos));
+        if (rate - 1.0).abs() > f64::EPSILON
+        {
+            config.l10n.write(Message::PlayingAtRate(rate));
+        }
+    }
+    if let Repeat::Section(start, end) = config.repeat
+    {
+        config
+            .l10n
+            .write(Message::Looping(start.as_secs_f64(), end.as_secs_f64()));
+    }
+    if let Some(timer) = config.sleep
+    {
+        show_sleep_timer(config, timer);
     }
 
     BigAction::Nothing
@@ -185,16 +280,35 @@ fn pause_after_song(config: &mut Config) -> BigAction
 
 fn show_info(config: &mut Config) -> BigAction
 {
-    print_info(&config.tag, config.l10n);
+    config.read_tags();
+    print_info(&config.metadata, config.l10n);
 
     BigAction::Nothing
 }
 
 fn open_cover(config: &mut Config) -> BigAction
 {
-    if let Ok(pic_path) = config.arc_config.pic_path.lock()
+    config.read_tags();
+    // The one set by MPRIS is at least the default picture.
+    let cover = match &config.metadata
     {
-        if let Some(pic_path) = pic_path.clone()
+        Some(Ok(metadata)) => cover(&metadata.path, Some(metadata), &config.arc_config),
+        _ => None,
+    };
+    let pic_path = match cover
+    {
+        Some(cover) => Ok(Some(cover)),
+        None => config
+            .arc_config
+            .pic_path
+            .lock()
+            .map(|pic_path| pic_path.clone().map(PathBuf::from))
+            .map_err(drop),
+    };
+
+    match pic_path
+    {
+        Ok(Some(pic_path)) =>
         {
             config.l10n.write(Message::OpeningPicture);
             thread::spawn(|| {
@@ -205,25 +319,34 @@ fn open_cover(config: &mut Config) -> BigAction
                     .and_then(|mut handle| handle.wait());
             });
         }
-        else
-        {
-            config.l10n.write(Message::NothingPlayingYet);
-        }
-    }
-    else
-    {
-        config.l10n.write(Message::CantOpenPicture);
+        Ok(None) => config.l10n.write(Message::NothingPlayingYet),
+        Err(()) => config.l10n.write(Message::CantOpenPicture),
     }
 
     BigAction::Nothing
 }
 
+// Ends a loop inside the current song, so that it goes on normally.
+fn stop_loop(config: &mut Config)
+{
+    if matches!(config.repeat, Repeat::Section(_, _))
+    {
+        config.source.clear_loop();
+        config.repeat = Repeat::Not;
+        config.l10n.write(Message::LoopCleared);
+    }
+}
+
 fn disable_repeat(config: &mut Config) -> BigAction
 {
     if config.repeat == Repeat::Not
     {
         config.l10n.write(Message::NotRepeatingAlready);
     }
+    else if matches!(config.repeat, Repeat::Section(_, _))
+    {
+        stop_loop(config);
+    }
     else
     {
         config.song_index += 1;
@@ -242,6 +365,7 @@ fn repeat_once(config: &mut Config) -> BigAction
     }
     else
     {
+        stop_loop(config);
         if config.repeat == Repeat::Not
         {
             config.song_index -= 1;
@@ -261,6 +385,7 @@ fn repeat_forever(config: &mut Config) -> BigAction
     }
     else
     {
+        stop_loop(config);
         if config.repeat == Repeat::Not
         {
             config.song_index -= 1;
@@ -287,6 +412,386 @@ fn skip_to_previous(config: &mut Config) -> BigAction
     BigAction::Nothing
 }
 
+// Samples (over all channels) per second of the current song.
+fn samples_per_sec(config: &Config) -> usize
+{
+    config.source.sample_rate as usize * config.arc_config.channels.load(Ordering::SeqCst)
+}
+
+fn seek_to_sample(config: &mut Config, pos: usize) -> BigAction
+{
+    // Only a seek out of the loop ends it.
+    let in_loop = matches!(config.repeat, Repeat::Section(_, _)) && config.source.seek_in_loop(pos);
+    if !in_loop
+    {
+        stop_loop(config);
+    }
+    if in_loop || config.source.seek(pos)
+    {
+        config.l10n.write(Message::SeekingTo(
+            pos as f64 / samples_per_sec(config).max(1) as f64,
+        ));
+    }
+    else
+    {
+        config.l10n.write(Message::NothingPlayingYet);
+    }
+
+    BigAction::Nothing
+}
+
+fn seek_by(config: &mut Config, secs: i64) -> BigAction
+{
+    let pos = config.source.get_pos();
+    let delta = secs.unsigned_abs() as usize * samples_per_sec(config);
+    let pos = if secs < 0
+    {
+        pos.saturating_sub(delta)
+    }
+    else
+    {
+        pos.saturating_add(delta)
+    };
+
+    seek_to_sample(config, pos)
+}
+
+fn seek_forward(config: &mut Config) -> BigAction
+{
+    seek_by(config, 10)
+}
+
+fn seek_backward(config: &mut Config) -> BigAction
+{
+    seek_by(config, -10)
+}
+
+fn seek_forward_far(config: &mut Config) -> BigAction
+{
+    seek_by(config, 60)
+}
+
+fn seek_backward_far(config: &mut Config) -> BigAction
+{
+    seek_by(config, -60)
+}
+
+fn seek_to(config: &mut Config) -> BigAction
+{
+    // `seek_target` is in microseconds.
+    let target = config.arc_config.seek_target.load(Ordering::SeqCst) as u128;
+    let pos = target * samples_per_sec(config) as u128 / 1_000_000;
+
+    seek_to_sample(config, pos as usize)
+}
+
+// How much the rate commands change the playback rate.
+const RATE_STEP: f32 = 0.1;
+
+// Rounds to whole percents, so that repeated steps don't accumulate
+// rounding errors.
+fn round_rate(rate: f32) -> f32
+{
+    (rate * 100.0).round() / 100.0
+}
+
+fn change_rate(config: &mut Config, by: f32) -> BigAction
+{
+    config.rate = round_rate((config.rate + by).clamp(MIN_RATE, MAX_RATE));
+    config.apply_rate();
+    config
+        .l10n
+        .write(Message::PlayingAtRate(config.effective_rate() as f64));
+
+    BigAction::Nothing
+}
+
+fn increase_rate(config: &mut Config) -> BigAction
+{
+    change_rate(config, RATE_STEP)
+}
+
+fn decrease_rate(config: &mut Config) -> BigAction
+{
+    change_rate(config, -RATE_STEP)
+}
+
+fn set_rate(config: &mut Config) -> BigAction
+{
+    // The requested rate is the one that should be heard, so the rate
+    // of the current song has to be taken into account.
+    let target = f32::from_bits(config.arc_config.rate_target.load(Ordering::SeqCst));
+
+    config.rate = (target / config.song_rate).clamp(MIN_RATE, MAX_RATE);
+    config.apply_rate();
+    config
+        .l10n
+        .write(Message::PlayingAtRate(config.effective_rate() as f64));
+
+    BigAction::Nothing
+}
+
+fn change_song_rate(config: &mut Config, by: f32) -> BigAction
+{
+    config.song_rate = round_rate((config.song_rate + by).clamp(MIN_RATE, MAX_RATE));
+    config.settings_changed = true;
+    config.apply_rate();
+    config
+        .l10n
+        .write(Message::SongRate(config.song_rate as f64));
+
+    BigAction::Nothing
+}
+
+fn increase_song_rate(config: &mut Config) -> BigAction
+{
+    change_song_rate(config, RATE_STEP)
+}
+
+fn decrease_song_rate(config: &mut Config) -> BigAction
+{
+    change_song_rate(config, -RATE_STEP)
+}
+
+// Switches the current song to the next equalizer preset; after the
+// last one it uses the default again.
+fn cycle_equalizer(config: &mut Config) -> BigAction
+{
+    let presets = &config.arc_config.presets;
+    let next = config.equalizer.as_ref().map_or_else(
+        || presets.first(),
+        |name| {
+            presets
+                .iter()
+                .position(|preset| preset.name == *name)
+                .and_then(|i| presets.get(i + 1))
+        },
+    );
+
+    config.equalizer = next.map(|preset| preset.name.clone());
+    config.settings_changed = true;
+    config
+        .source
+        .set_equalizer(find_preset(config.equalizer.as_deref(), &config.arc_config));
+
+    let default = config.arc_config.conffile.equalizer.as_deref();
+    let message = config.equalizer.clone().map_or_else(
+        || Message::EqualizerDefault(default.unwrap_or(FLAT).to_string()),
+        Message::Equalizer,
+    );
+    config.l10n.write(message);
+
+    BigAction::Nothing
+}
+
+// The current position as `Duration`; `None` if nothing is played.
+fn current_duration(config: &mut Config) -> Option<Duration>
+{
+    let pos = config.source.get_pos();
+
+    config.source.pos_to_duration(pos)
+}
+
+fn start_here(config: &mut Config) -> BigAction
+{
+    let Some(pos) = current_duration(config)
+    else
+    {
+        config.l10n.write(Message::NothingPlayingYet);
+        return BigAction::Nothing;
+    };
+
+    config.start = Some(pos);
+    config.settings_changed = true;
+    config.source.set_trim(config.start, config.end);
+    config.l10n.write(Message::StartingAt(pos.as_secs_f64()));
+
+    BigAction::Nothing
+}
+
+fn end_here(config: &mut Config) -> BigAction
+{
+    let Some(pos) = current_duration(config)
+    else
+    {
+        config.l10n.write(Message::NothingPlayingYet);
+        return BigAction::Nothing;
+    };
+
+    config.end = Some(pos);
+    config.settings_changed = true;
+    config.source.set_trim(config.start, config.end);
+    config.l10n.write(Message::EndingAt(pos.as_secs_f64()));
+
+    BigAction::Nothing
+}
+
+fn play_completely(config: &mut Config) -> BigAction
+{
+    // Not `None`, so that the silence isn't detected again.
+    config.start = Some(Duration::ZERO);
+    config.end = None;
+    config.settings_changed = true;
+    config.source.set_trim(config.start, config.end);
+    config.l10n.write(Message::PlayingCompletely);
+
+    BigAction::Nothing
+}
+
+// Without an argument (or with zero minutes) the sleep timer is
+// cancelled.
+fn after_time(config: &mut Config, pause: bool) -> BigAction
+{
+    let minutes = config.arc_config.sleep_minutes.load(Ordering::SeqCst);
+
+    if minutes == 0
+    {
+        if config.sleep.is_some()
+        {
+            config.set_sleep_timer(None);
+            config.apply_volume();
+            config.l10n.write(Message::SleepTimerCancelled);
+        }
+        else
+        {
+            config.l10n.write(Message::NoSleepTimer);
+        }
+    }
+    else
+    {
+        let timer = SleepTimer::new(minutes, pause);
+        config.set_sleep_timer(Some(timer));
+        config.apply_volume();
+        show_sleep_timer(config, timer);
+    }
+
+    BigAction::Nothing
+}
+
+fn loop_start(config: &mut Config) -> BigAction
+{
+    let pos = config.source.get_pos();
+    let Some(start) = config
+        .source
+        .set_loop_start(pos)
+        .and_then(|start| config.source.pos_to_duration(start))
+    else
+    {
+        config.l10n.write(Message::NothingPlayingYet);
+        return BigAction::Nothing;
+    };
+
+    // The old loop is over; the song goes on after it.
+    if matches!(config.repeat, Repeat::Section(_, _))
+    {
+        config.repeat = Repeat::Not;
+    }
+    config
+        .l10n
+        .write(Message::LoopStartMarked(start.as_secs_f64()));
+
+    BigAction::Nothing
+}
+
+fn loop_end(config: &mut Config) -> BigAction
+{
+    let pos = config.source.get_pos();
+    let max = config.source.ms_to_samples(MAX_LOOP.as_millis() as u64);
+
+    match config.source.loop_start()
+    {
+        Some(start) if start < pos && pos - start <= max =>
+        {
+            config.source.set_loop_end(pos);
+            let start = config.source.pos_to_duration(start).unwrap_or_default();
+            let end = config.source.pos_to_duration(pos).unwrap_or_default();
+            // Otherwise the next song would be the current one again.
+            if matches!(config.repeat, Repeat::Once | Repeat::Always)
+            {
+                config.song_index += 1;
+            }
+            config.repeat = Repeat::Section(start, end);
+            config
+                .l10n
+                .write(Message::Looping(start.as_secs_f64(), end.as_secs_f64()));
+        }
+        Some(start) if start < pos => config.l10n.write(Message::LoopTooLong),
+        _ => config.l10n.write(Message::NoLoopStart),
+    }
+
+    BigAction::Nothing
+}
+
+fn toggle_remember(config: &mut Config) -> BigAction
+{
+    let remember = !config.remembers_position();
+
+    config.remember = Some(remember);
+    config.settings_changed = true;
+    config.l10n.write(
+        if remember
+        {
+            Message::RememberingPosition
+        }
+        else
+        {
+            Message::NotRememberingPosition
+        },
+    );
+
+    BigAction::Nothing
+}
+
+fn edit_tags(config: &mut Config) -> BigAction
+{
+    let edit = config
+        .arc_config
+        .tag_edit
+        .lock()
+        .ok()
+        .and_then(|mut edit| edit.take());
+
+    if let Some(edit) = edit
+    {
+        config.edit_tags(&edit);
+    }
+
+    BigAction::Nothing
+}
+
+fn toggle_lyrics(config: &mut Config) -> BigAction
+{
+    config.lyrics = !config.lyrics;
+
+    if config.lyrics
+    {
+        config.l10n.write(Message::ShowingLyrics);
+        // The current line is shown right away.
+        config.lyrics_line = None;
+        config.read_tags();
+        if !matches!(&config.metadata, Some(Ok(metadata)) if !metadata.sync_lyrics.is_empty())
+        {
+            config.l10n.write(Message::NoSyncLyrics);
+        }
+    }
+    else
+    {
+        config.l10n.write(Message::NotShowingLyrics);
+    }
+
+    BigAction::Nothing
+}
+
+fn quit_after_time(config: &mut Config) -> BigAction
+{
+    after_time(config, false)
+}
+
+fn pause_after_time(config: &mut Config) -> BigAction
+{
+    after_time(config, true)
+}
+
 impl Command
 {
     pub fn get_handler(self) -> fn(&mut Config) -> BigAction
@@ -311,6 +816,27 @@ impl Command
             Self::RepeatOnce => repeat_once,
             Self::RepeatForever => repeat_forever,
             Self::SkipToPrevious => skip_to_previous,
+            Self::SeekForward => seek_forward,
+            Self::SeekBackward => seek_backward,
+            Self::SeekForwardFar => seek_forward_far,
+            Self::SeekBackwardFar => seek_backward_far,
+            Self::SeekTo => seek_to,
+            Self::IncreaseRate => increase_rate,
+            Self::DecreaseRate => decrease_rate,
+            Self::SetRate => set_rate,
+            Self::IncreaseSongRate => increase_song_rate,
+            Self::DecreaseSongRate => decrease_song_rate,
+            Self::CycleEqualizer => cycle_equalizer,
+            Self::StartHere => start_here,
+            Self::EndHere => end_here,
+            Self::PlayCompletely => play_completely,
+            Self::QuitAfterTime => quit_after_time,
+            Self::PauseAfterTime => pause_after_time,
+            Self::LoopStart => loop_start,
+            Self::LoopEnd => loop_end,
+            Self::ToggleRemember => toggle_remember,
+            Self::EditTags => edit_tags,
+            Self::ToggleLyrics => toggle_lyrics,
         }
     }
 }
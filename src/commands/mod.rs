@@ -28,6 +28,27 @@ pub enum Command
     RepeatOnce,
     RepeatForever,
     SkipToPrevious,
+    SeekForward,
+    SeekBackward,
+    SeekForwardFar,
+    SeekBackwardFar,
+    SeekTo,
+    IncreaseRate,
+    DecreaseRate,
+    SetRate,
+    IncreaseSongRate,
+    DecreaseSongRate,
+    CycleEqualizer,
+    StartHere,
+    EndHere,
+    PlayCompletely,
+    QuitAfterTime,
+    PauseAfterTime,
+    LoopStart,
+    LoopEnd,
+    ToggleRemember,
+    EditTags,
+    ToggleLyrics,
 }
 
 #[derive(Clone, Copy)]
@@ -43,13 +64,39 @@ impl fmt::Display for DisplayCommand
 
 impl Command
 {
+    // Commands are numbered through alphabetically; after `z` it
+    // continues with the uppercase letters.
+    pub fn from_char(c: u8) -> Option<Self>
+    {
+        match c
+        {
+            b'a'..=b'z' => Self::try_from(c - b'a').ok(),
+            b'A'..=b'Z' => Self::try_from(c - b'A' + 26).ok(),
+            _ => None,
+        }
+    }
+
+    pub const fn to_char(self) -> char
+    {
+        let i = self as u8;
+
+        if i < 26
+        {
+            (i + b'a') as char
+        }
+        else
+        {
+            (i - 26 + b'A') as char
+        }
+    }
+
     pub fn show_help(lang: L10n)
     {
         lang.write(Message::HelpHeader);
-        for (i, command) in (0..).map_while(|i| Self::try_from(i).map(|c| (i, c)).ok())
+        for command in (0..).map_while(|i| Self::try_from(i).ok())
         {
-            debug_assert!(i < 26);
-            println!("{}): {}", (i + b'a') as char, DisplayCommand(command, lang));
+            debug_assert!((command as u8) < 52);
+            println!("{}): {}", command.to_char(), DisplayCommand(command, lang));
         }
     }
 }
@@ -3,8 +3,12 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{csv::Csv, err::Error, l10n::Lang};
+use crate::{
+    alarm::AlarmTime, csv::Csv, err::Error, l10n::Lang, loudness::Normalization, output::OutputKind,
+};
 
+// They are all independent options.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug)]
 pub struct Conffile
 {
@@ -14,6 +18,32 @@ pub struct Conffile
     pub lang: Lang,
     pub repeat_bonus: i64,
     pub enable_dbus: bool,
+    pub read_ahead: u64,
+    pub crossfade_ms: u64,
+    pub crossfade_repeat: bool,
+    pub skip_fade_ms: u64,
+    pub normalization: Normalization,
+    pub loudness_target: f32,
+    pub rate: f32,
+    pub equalizer: Option<String>,
+    pub detect_silence: bool,
+    pub output: OutputKind,
+    pub output_device: Option<String>,
+    pub output_file: PathBuf,
+    pub output_speed: f32,
+    pub alarm: Option<AlarmTime>,
+    pub alarm_ramp_ms: u64,
+    pub alarm_songs: Option<PathBuf>,
+    pub resume_min_secs: u64,
+    pub resume_dirs: Vec<PathBuf>,
+    pub continue_song: bool,
+    // In MiB.
+    pub art_cache_size: u64,
+    // How long a parser plugin may take if it doesn't say otherwise.
+    pub parser_timeout_ms: u64,
+    // Listens are only submitted if there's a token.
+    pub listenbrainz_token: Option<String>,
+    pub listenbrainz_url: String,
 }
 
 impl Conffile
@@ -27,9 +57,34 @@ impl Conffile
             lang: Lang::English,
             repeat_bonus: 0,
             enable_dbus: false,
+            read_ahead: 16 * 1024 * 1024,
+            crossfade_ms: 0,
+            crossfade_repeat: false,
+            skip_fade_ms: 300,
+            normalization: Normalization::Off,
+            loudness_target: -18.0,
+            rate: 1.0,
+            equalizer: None,
+            detect_silence: false,
+            output: OutputKind::Device,
+            output_device: None,
+            output_file: conffile_dir.join("output.wav"),
+            output_speed: 1.0,
+            alarm: None,
+            alarm_ramp_ms: 60_000,
+            alarm_songs: None,
+            resume_min_secs: 1800,
+            resume_dirs: vec![],
+            continue_song: false,
+            art_cache_size: 50,
+            parser_timeout_ms: 2000,
+            listenbrainz_token: None,
+            listenbrainz_url: String::from("https://api.listenbrainz.org"),
         }
     }
 
+    // Every option is just another arm of the match.
+    #[allow(clippy::too_many_lines)]
     pub fn new(conffile_dir: &Path) -> Result<Self, Error>
     {
         let mut rv = Self::default(conffile_dir);
@@ -78,6 +133,86 @@ impl Conffile
                 {
                     let _ = line[1].parse().map(|x| rv.enable_dbus = x);
                 }
+                "read_ahead" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.read_ahead = x);
+                }
+                "crossfade_ms" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.crossfade_ms = x);
+                }
+                "crossfade_repeat" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.crossfade_repeat = x);
+                }
+                "skip_fade_ms" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.skip_fade_ms = x);
+                }
+                "normalization" => match line[1].as_str()
+                {
+                    "off" => rv.normalization = Normalization::Off,
+                    "propose" => rv.normalization = Normalization::Propose,
+                    "apply" => rv.normalization = Normalization::Apply,
+                    _ =>
+                    {}
+                },
+                "loudness_target" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.loudness_target = x);
+                }
+                "rate" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.rate = x);
+                }
+                "equalizer" => rv.equalizer = Some(take(&mut line[1])),
+                "detect_silence" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.detect_silence = x);
+                }
+                "output" => match line[1].as_str()
+                {
+                    "device" => rv.output = OutputKind::Device,
+                    "null" => rv.output = OutputKind::Null,
+                    "wav" => rv.output = OutputKind::Wav,
+                    _ =>
+                    {}
+                },
+                "output_device" => rv.output_device = Some(take(&mut line[1])),
+                "output_file" => rv.output_file = PathBuf::from(take(&mut line[1])),
+                "output_speed" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.output_speed = x);
+                }
+                "alarm" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.alarm = Some(x));
+                }
+                "alarm_ramp_ms" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.alarm_ramp_ms = x);
+                }
+                "alarm_songs" => rv.alarm_songs = Some(PathBuf::from(take(&mut line[1]))),
+                "resume_min_secs" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.resume_min_secs = x);
+                }
+                // Can be given more than once.
+                "resume_dir" => rv.resume_dirs.push(PathBuf::from(take(&mut line[1]))),
+                "continue_song" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.continue_song = x);
+                }
+                "art_cache_size" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.art_cache_size = x);
+                }
+                "parser_timeout_ms" =>
+                {
+                    let _ = line[1].parse().map(|x| rv.parser_timeout_ms = x);
+                }
+                "listenbrainz_token" => rv.listenbrainz_token = Some(take(&mut line[1])),
+                "listenbrainz_url" => rv.listenbrainz_url = take(&mut line[1]),
                 _ =>
                 {}
             }
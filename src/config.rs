@@ -1,40 +1,109 @@
 use std::{
+    collections::HashMap,
     path::PathBuf,
     sync::{
-        atomic::{AtomicBool, AtomicUsize},
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
         mpsc::{channel, Receiver, Sender},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
-use id3::Tag;
-use rodio::{OutputStream, OutputStreamHandle, Sink};
+use rodio::Sink;
 
 use crate::{
-    audio::ChannelAudio, commands::Command, conffile::Conffile, files::ensure_file_existence,
-    l10n::L10n, songs::Repeat, Error,
+    alarm::{alarm_from_args, Ramp},
+    audio::ChannelAudio,
+    commands::Command,
+    conffile::Conffile,
+    equalizer::{read_presets, Preset},
+    files::ensure_file_existence,
+    hooks::{start_hooks, HookEnv},
+    l10n::{messages::Message, L10n},
+    library::Library,
+    metadata::Metadata,
+    output::Output,
+    parser::Server,
+    scrobble::Listens,
+    sleep::SleepTimer,
+    songs::{NextSong, Repeat},
+    tagging::TagEdit,
+    Error,
 };
 
+/// The playback rate can't be set outside of these bounds.
+pub const MIN_RATE: f32 = 0.25;
+pub const MAX_RATE: f32 = 4.0;
+
+// They are all independent flags.
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config
 {
     pub sink: Sink,
-    pub stream_handle: OutputStreamHandle,
-    pub _stream: OutputStream,
+    // Sinks of songs which are still fading out.
+    pub fading: Vec<Sink>,
+    pub output: Output,
     pub source: ChannelAudio,
+    pub next: Option<NextSong>,
     pub tx: Sender<Command>,
     pub rx: Receiver<Command>,
+    // Results of the loudness scanner; see `loudness::scan`.
+    pub tx_loudness: Sender<(usize, f32)>,
+    pub rx_loudness: Receiver<(usize, f32)>,
     pub rx_control: Receiver<()>,
     pub tx_paused: Sender<bool>,
-    pub tx_path: Sender<(PathBuf, Option<Tag>)>,
-    pub tag: Option<Result<Tag, id3::Error>>,
+    pub tx_path: Sender<(PathBuf, Option<Metadata>)>,
+    // What happened for the hooks; see `hooks::start_hooks`.
+    pub tx_hooks: Sender<HookEnv>,
+    // The song that is played right now (or was last).
+    pub song_path: Option<PathBuf>,
+    pub metadata: Option<Result<Metadata, Error>>,
+    // Whether `metadata` has everything from the tags or only what's
+    // in the index of the library; see `Config::read_tags`.
+    pub tags_read: bool,
+    // Whether the synchronized lyrics are shown while playing and
+    // which line was shown last.
+    pub lyrics: bool,
+    pub lyrics_line: Option<usize>,
     pub num: u32,
     pub loud: f32,
+    // What `loud` is multiplied with to get the volume of the sink.
+    pub gain: f32,
+    // The global playback rate and the one of the current song; they
+    // are multiplied.
+    pub rate: f32,
+    pub song_rate: f32,
+    // The preset of the current song; `None` if it uses the default.
+    pub equalizer: Option<String>,
+    // Where the current song starts and ends.
+    pub start: Option<Duration>,
+    pub end: Option<Duration>,
+    // Whether the position in the current song is remembered; see
+    // `Song::remember`.
+    pub remember: Option<bool>,
+    pub remember_by_default: bool,
+    // Set when the current song is skipped, so that its position is
+    // still remembered.
+    pub skipped: bool,
+    // Set when one of the settings of the current song above (e.g. its
+    // likelihood or volume) changes, so that they are only stored in
+    // it then.
+    pub settings_changed: bool,
     pub paused: bool,
     pub pause_after_song: bool,
     pub quit_after_song: bool,
+    pub sleep: Option<SleepTimer>,
+    // Set while the volume is raised after the alarm went off.
+    pub alarm_ramp: Option<Ramp>,
+    // Only songs in this directory (in `data_dir`) are chosen; see
+    // `Conffile::alarm_songs`.
+    pub only_songs: Option<PathBuf>,
     pub repeat: Repeat,
     pub songlist: Vec<usize>,
     pub song_index: usize,
+    // Where the song that is played right now is in `songlist`;
+    // `None` after it ended.
+    pub playing: Option<usize>,
     pub arc_config: Arc<ArcConfig>,
     pub l10n: L10n,
     pub unsuccessful_tries: u8,
@@ -49,12 +118,40 @@ pub struct ArcConfig
     pub update_dbus: AtomicBool,
     pub current_pos: AtomicUsize,
     pub current_len: AtomicUsize,
+    // Every sample a song produced; the headless output doesn't write
+    // anything while it stays the same (e.g. while pausing).
+    pub samples_played: AtomicUsize,
     pub sample_rate: AtomicUsize,
     pub channels: AtomicUsize,
     pub monotonic_song_index: AtomicUsize,
+    pub active_song: AtomicUsize,
+    // In microseconds, since this is what MPRIS uses.
+    pub seek_target: AtomicUsize,
+    pub seeked: AtomicBool,
+    // Bits of an `f32`; the playback rate the current song is
+    // actually played with and the one requested by
+    // `Command::SetRate`.
+    pub rate: AtomicU32,
+    pub rate_target: AtomicU32,
+    // The argument of `Command::QuitAfterTime` and
+    // `Command::PauseAfterTime` and when the sleep timer runs out.
+    pub sleep_minutes: AtomicUsize,
+    pub sleep_end: Mutex<Option<Instant>>,
+    // What was typed for `Command::EditTags`.
+    pub tag_edit: Mutex<Option<TagEdit>>,
+    // The metadata index of all songs; filled by `Songs::read`.
+    pub library: Mutex<Library>,
+    // The parser plugins in server mode that are already running;
+    // every one is locked on its own while it's asked.
+    pub parser_servers: Mutex<HashMap<PathBuf, Arc<Mutex<Server>>>>,
+    // The listens that weren't submitted to ListenBrainz yet.
+    pub listens: Mutex<Listens>,
+    pub track_id: Mutex<Option<String>>,
     pub home_dir: PathBuf,
     pub config_dir: PathBuf,
     pub conffile: Conffile,
+    // The equalizer presets; the first one is always `flat`.
+    pub presets: Vec<Arc<Preset>>,
     pub l10n: L10n,
 }
 
@@ -65,9 +162,16 @@ impl ArcConfig
         let home_dir = home::home_dir().unwrap_or_else(|| PathBuf::from("./"));
         ensure_file_existence(&home_dir)?;
         let conffile_dir = home_dir.join(PathBuf::from("./.zvavybir/legacylisten"));
-        let conffile =
+        let mut conffile =
             Conffile::new(&conffile_dir).unwrap_or_else(|_| Conffile::default(&conffile_dir));
         let l10n = L10n::new(conffile.lang.clone())?;
+        if let Some(alarm) = alarm_from_args(l10n)
+        {
+            conffile.alarm = Some(alarm);
+        }
+        let presets = read_presets(&conffile_dir);
+        let library = Library::read(&conffile_dir);
+        let listens = Listens::read(&conffile_dir);
 
         Ok(Self {
             pic_path: Mutex::new(None),
@@ -75,12 +179,26 @@ impl ArcConfig
             update_dbus: AtomicBool::new(false),
             current_pos: AtomicUsize::new(0),
             current_len: AtomicUsize::new(0),
+            samples_played: AtomicUsize::new(0),
             sample_rate: AtomicUsize::new(1),
             channels: AtomicUsize::new(1),
             monotonic_song_index: AtomicUsize::new(0),
+            active_song: AtomicUsize::new(0),
+            seek_target: AtomicUsize::new(0),
+            seeked: AtomicBool::new(false),
+            rate: AtomicU32::new(1.0_f32.to_bits()),
+            rate_target: AtomicU32::new(1.0_f32.to_bits()),
+            sleep_minutes: AtomicUsize::new(0),
+            sleep_end: Mutex::new(None),
+            tag_edit: Mutex::new(None),
+            library: Mutex::new(library),
+            parser_servers: Mutex::new(HashMap::new()),
+            listens: Mutex::new(listens),
+            track_id: Mutex::new(None),
             home_dir,
             config_dir: conffile_dir,
             conffile,
+            presets,
             l10n,
         })
     }
@@ -91,41 +209,114 @@ impl Config
     pub fn new(
         rx_control: Receiver<()>,
         tx_paused: Sender<bool>,
-        tx_path: Sender<(PathBuf, Option<Tag>)>,
+        tx_path: Sender<(PathBuf, Option<Metadata>)>,
     ) -> Result<Self, Error>
     {
-        let (stream, stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
-        let (tx, rx) = channel();
         let arc_config = Arc::new(ArcConfig::new()?);
         let l10n = arc_config.l10n;
+        let output = Output::new(&arc_config)?;
+        let sink = output.new_sink()?;
+        let (tx, rx) = channel();
+        let (tx_loudness, rx_loudness) = channel();
+        let tx_hooks = start_hooks(&arc_config);
 
         Ok(Self {
             sink,
-            stream_handle,
-            _stream: stream,
+            fading: vec![],
+            output,
             source: ChannelAudio {
                 sample_rate: 0,
                 inner: None,
                 config: arc_config.clone(),
+                seek_tx: None,
+                state: None,
             },
+            next: None,
             tx,
             rx,
+            tx_loudness,
+            rx_loudness,
             rx_control,
             tx_paused,
             tx_path,
-            tag: None,
+            tx_hooks,
+            song_path: None,
+            metadata: None,
+            tags_read: false,
+            lyrics: false,
+            lyrics_line: None,
             num: 0,
             loud: 0.0,
+            gain: 1.0,
+            rate: arc_config.conffile.rate.clamp(MIN_RATE, MAX_RATE),
+            song_rate: 1.0,
+            equalizer: None,
+            start: None,
+            end: None,
+            remember: None,
+            remember_by_default: false,
+            skipped: false,
+            settings_changed: false,
             paused: false,
             pause_after_song: false,
             quit_after_song: false,
+            sleep: None,
+            alarm_ramp: None,
+            only_songs: None,
             repeat: Repeat::Not,
             songlist: vec![],
             song_index: 0,
+            playing: None,
             arc_config,
             l10n,
             unsuccessful_tries: 0,
         })
     }
 }
+
+impl Config
+{
+    /// The rate the current song is actually played with.
+    pub fn effective_rate(&self) -> f32
+    {
+        (self.rate * self.song_rate).clamp(MIN_RATE, MAX_RATE)
+    }
+
+    /// What the volume is multiplied with because of the sleep timer
+    /// and the alarm.
+    pub fn fade(&self) -> f32
+    {
+        self.sleep.as_ref().map_or(1.0, SleepTimer::fade)
+            * self.alarm_ramp.as_ref().map_or(1.0, Ramp::fade)
+    }
+
+    /// Makes the sink play with the current volume (including the
+    /// fades of the sleep timer and the alarm).
+    pub fn apply_volume(&self)
+    {
+        self.sink.set_volume(self.loud * self.gain * self.fade());
+    }
+
+    /// Makes the sink play with the current rate.
+    pub fn apply_rate(&self)
+    {
+        let rate = self.effective_rate();
+
+        self.sink.set_speed(rate);
+        self.arc_config.rate.store(rate.to_bits(), Ordering::SeqCst);
+        self.arc_config.update_dbus.store(true, Ordering::SeqCst);
+    }
+
+    /// Creates another sink for fading, e.g. when crossfading; `None`
+    /// (after saying so) if the output device disappeared, so that
+    /// it's cut instead.
+    pub fn new_sink(&self) -> Option<Sink>
+    {
+        self.output
+            .new_sink()
+            .map_err(|err| {
+                self.l10n.write(Message::NoNewSink(format!("{err:?}")));
+            })
+            .ok()
+    }
+}
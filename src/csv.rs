@@ -5,6 +5,7 @@ use std::{
     mem,
     path::Path,
     sync::Arc,
+    time::Duration,
 };
 
 use crate::{
@@ -14,6 +15,20 @@ use crate::{
     songs::{L10nHelper, Song, Songs},
 };
 
+// Durations are stored in seconds; empty if unknown.
+pub fn parse_duration(field: Option<&String>) -> Option<Duration>
+{
+    field
+        .and_then(|x| x.parse().ok())
+        .and_then(|x| Duration::try_from_secs_f64(x).ok())
+}
+
+// Takes a field; `None` if it's empty.
+pub fn non_empty(field: &mut String) -> Option<String>
+{
+    Some(mem::take(field)).filter(|x| !x.is_empty())
+}
+
 #[derive(Clone, Debug)]
 pub struct Csv
 {
@@ -100,6 +115,22 @@ impl Csv
                     song.name.clone(),
                     song.num.to_string(),
                     song.loud.to_string(),
+                    song.loudness.map(|x| x.to_string()).unwrap_or_default(),
+                    song.duration
+                        .map(|x| x.as_secs_f64().to_string())
+                        .unwrap_or_default(),
+                    song.rate.to_string(),
+                    song.equalizer.clone().unwrap_or_default(),
+                    song.start
+                        .map(|x| x.as_secs_f64().to_string())
+                        .unwrap_or_default(),
+                    song.end
+                        .map(|x| x.as_secs_f64().to_string())
+                        .unwrap_or_default(),
+                    song.bookmark
+                        .map(|x| x.as_secs_f64().to_string())
+                        .unwrap_or_default(),
+                    song.remember.map(|x| x.to_string()).unwrap_or_default(),
                 ]
             })
             .collect();
@@ -110,7 +141,12 @@ impl Csv
     #[must_use]
     pub fn get_songs(self, config: Arc<ArcConfig>, l10n: L10n) -> Option<Songs>
     {
-        if self.entries.iter().all(|song| song.len() == 3)
+        // Older files have less columns; the missing ones are just
+        // not known yet.
+        if self
+            .entries
+            .iter()
+            .all(|song| (3..=11).contains(&song.len()))
         {
             Some(Songs {
                 songs: self
@@ -120,6 +156,14 @@ impl Csv
                         name: mem::take(&mut song[0]),
                         num: song[1].parse().unwrap(),
                         loud: song[2].parse().unwrap(),
+                        rate: song.get(5).and_then(|x| x.parse().ok()).unwrap_or(1.0),
+                        loudness: song.get(3).and_then(|x| x.parse().ok()),
+                        duration: parse_duration(song.get(4)),
+                        equalizer: song.get_mut(6).map(mem::take).filter(|x| !x.is_empty()),
+                        start: parse_duration(song.get(7)),
+                        end: parse_duration(song.get(8)),
+                        bookmark: parse_duration(song.get(9)),
+                        remember: song.get(10).and_then(|x| x.parse().ok()),
                     })
                     .collect(),
                 config,
@@ -1,8 +1,5 @@
 use std::{
     collections::HashMap,
-    ffi::OsStr,
-    fs::File,
-    io::Write,
     iter::FromIterator,
     path::{Path, PathBuf},
     sync::{
@@ -11,24 +8,25 @@ use std::{
         Arc, Mutex,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use dbus::{
     arg::{messageitem::MessageItem, Variant},
     blocking::Connection,
-    channel::MatchingReceiver,
+    channel::{MatchingReceiver, Sender as _},
+    strings::{Interface, Member},
     MethodErr,
 };
 use dbus_crossroads::{Crossroads, IfaceBuilder};
-use id3::{Tag, TagLike};
 
 use crate::{
+    art::cover,
     commands::Command,
-    config::ArcConfig,
-    helpers::{recv_last, unwrap_two},
+    config::{ArcConfig, MAX_RATE, MIN_RATE},
+    helpers::recv_last,
     l10n::messages::Message,
-    parser::SmallMetadata,
+    metadata::Metadata,
 };
 
 fn path_to_dbus_obj(path: &Path) -> String
@@ -45,24 +43,25 @@ fn path_to_dbus_obj(path: &Path) -> String
     )
 }
 
-// Mostly the same code as above; TODO: Try avoid that.
-fn path_to_filename(path: &Path) -> String
+fn to_dbus_time(val: usize, config: &Arc<ArcConfig>) -> i64
 {
-    path.to_str().map_or_else(
-        || String::from("icon.art"),
-        |s| {
-            vec![String::from("icon.art_")]
-                .into_iter()
-                .chain(s.bytes().map(|x| format!("a{}", x)))
-                .collect::<Vec<_>>()
-                .concat()
-        },
+    ((val * 1000 * 1000) / config.sample_rate.load(Ordering::SeqCst)) as _
+}
+
+fn current_dbus_time(config: &Arc<ArcConfig>) -> i64
+{
+    to_dbus_time(
+        config.current_pos.load(Ordering::SeqCst) / config.channels.load(Ordering::SeqCst),
+        config,
     )
 }
 
-fn to_dbus_time(val: usize, config: &Arc<ArcConfig>) -> i64
+fn seek_to(tx: &Sender<Command>, config: &Arc<ArcConfig>, pos: i64)
 {
-    ((val * 1000 * 1000) / config.sample_rate.load(Ordering::SeqCst)) as _
+    config
+        .seek_target
+        .store(pos.max(0) as usize, Ordering::SeqCst);
+    let _ = tx.send(Command::SeekTo);
 }
 
 const fn itemify(x: String) -> Variant<MessageItem>
@@ -99,7 +98,7 @@ fn set_metadata(
 ) -> HashMap<String, Variant<MessageItem>>
 {
     let _ = tx_control.send(());
-    let (path, tag) = recv_last(&rx_path.lock().unwrap());
+    let (path, metadata) = recv_last(&rx_path.lock().unwrap());
 
     let art_url_raw = config
         .config_dir
@@ -117,49 +116,86 @@ fn set_metadata(
         *pic_path = Some(art_url_raw);
     }
 
+    let track_id = path_to_dbus_obj(&path);
+
+    if let Ok(mut current_track_id) = config.track_id.lock()
+    {
+        *current_track_id = Some(track_id.clone());
+    }
+
     let mut hm = HashMap::<_, _>::from_iter(vec![
-        (
-            String::from("mpris:trackid"),
-            itemify(path_to_dbus_obj(&path)),
-        ),
+        (String::from("mpris:trackid"), itemify(track_id)),
         // Fallback icon
         (String::from("mpris:artUrl"), itemify(art_url)),
     ]);
 
-    let title = tag
-        .as_ref()
-        .and_then(|tag| tag.artist().map(ToOwned::to_owned));
-    let artist = tag
-        .as_ref()
-        .and_then(|tag| tag.title().map(ToOwned::to_owned));
+    // Songs without tags are the same as songs with empty tags here.
+    let mut metadata = metadata.unwrap_or_default();
+    if metadata.title.is_none() || metadata.artist.is_none()
+    {
+        if let Some(entry) = config.indexed_parsed(&path)
+        {
+            entry.complete(&mut metadata);
+        }
+    }
+
+    let title = metadata
+        .title
+        .clone()
+        .unwrap_or_else(|| config.l10n.get(Message::UnknownTitle));
+    let artist = metadata
+        .artist
+        .clone()
+        .unwrap_or_else(|| config.l10n.get(Message::UnknownArtist));
 
-    let (title, artist) = unwrap_two(title, artist, || {
-        SmallMetadata::new(path.file_stem().and_then(OsStr::to_str), config).into_tuple()
-    });
+    hm.insert(String::from("xesam:artist"), itemify(artist));
 
-    hm.insert(String::from("xesam:artist"), itemify(title));
+    hm.insert(String::from("xesam:title"), itemify(title));
 
-    hm.insert(String::from("xesam:title"), itemify(artist));
+    let strings = [
+        ("xesam:album", metadata.album.clone()),
+        ("xesam:albumArtist", metadata.album_artist.clone()),
+        ("xesam:genre", metadata.genre.clone()),
+        (
+            "xesam:contentCreated",
+            metadata
+                .date_recorded
+                .clone()
+                .or_else(|| metadata.year.map(|year| year.to_string())),
+        ),
+    ];
+    for (key, value) in strings
+    {
+        if let Some(value) = value
+        {
+            hm.insert(String::from(key), itemify(value));
+        }
+    }
 
-    if let Some(picture) = tag.and_then(|tag| tag.pictures().next().cloned())
+    let numbers = [
+        ("xesam:trackNumber", metadata.track),
+        ("xesam:discNumber", metadata.disc),
+    ];
+    for (key, value) in numbers
     {
-        let path = config.config_dir.join(path_to_filename(&path));
-        if let Ok(mut file) = File::create(&path)
+        if let Some(value) = value.and_then(|value| i32::try_from(value).ok())
         {
-            if file.write_all(&picture.data).is_ok()
-            {
-                let art_url_raw = path.to_str().unwrap();
-                let mut art_url = String::from("file://");
-                art_url.push_str(art_url_raw);
+            hm.insert(String::from(key), Variant(MessageItem::Int32(value)));
+        }
+    }
 
-                if let Ok(mut pic_path) = config.pic_path.lock()
-                {
-                    *pic_path = Some(art_url_raw.to_string());
-                }
+    if let Some(cover) = cover(&path, Some(&metadata), config)
+    {
+        let art_url_raw = cover.to_str().unwrap();
+        let mut art_url = String::from("file://");
+        art_url.push_str(art_url_raw);
 
-                hm.insert(String::from("mpris:artUrl"), itemify(art_url));
-            }
+        if let Ok(mut pic_path) = config.pic_path.lock()
+        {
+            *pic_path = Some(art_url_raw.to_string());
         }
+
+        hm.insert(String::from("mpris:artUrl"), itemify(art_url));
     }
 
     if config.current_len.load(Ordering::SeqCst) != 0
@@ -176,7 +212,81 @@ fn set_metadata(
     hm
 }
 
-type ComplexReceiver = Receiver<(PathBuf, Option<Tag>)>;
+// Positions stay in track time; clients extrapolate them with the
+// rate themselves.
+fn register_rate(b: &mut IfaceBuilder<()>, tx: Sender<Command>, config: Arc<ArcConfig>)
+{
+    let config_clone = config.clone();
+    b.property("Rate")
+        .get(move |_, _| {
+            Ok(f64::from(f32::from_bits(
+                config_clone.rate.load(Ordering::SeqCst),
+            )))
+        })
+        .set(move |_, (), rate: f64| {
+            // NaN would get through every comparison and `clamp`.
+            if !rate.is_finite()
+            {
+                return Err(MethodErr::invalid_arg(&rate));
+            }
+            // A rate of zero means pausing according to the
+            // specification; negative ones aren't allowed.
+            if rate <= 0.0
+            {
+                let _ = tx.send(Command::Pause);
+            }
+            else
+            {
+                #[allow(clippy::cast_possible_truncation)]
+                config
+                    .rate_target
+                    .store((rate as f32).to_bits(), Ordering::SeqCst);
+                let _ = tx.send(Command::SetRate);
+            }
+            Ok(None)
+        });
+    b.property("MaximumRate")
+        .get(|_, _| Ok(f64::from(MAX_RATE)));
+    b.property("MinimumRate")
+        .get(|_, _| Ok(f64::from(MIN_RATE)));
+}
+
+// What isn't part of MPRIS.
+fn register_legacylisten(b: &mut IfaceBuilder<()>, tx: Sender<Command>, config: Arc<ArcConfig>)
+{
+    let config_clone = config.clone();
+    b.method(
+        "SetSleepTimer",
+        ("Minutes", "Pause"),
+        (),
+        move |_, (), (minutes, pause): (u32, bool)| {
+            config_clone
+                .sleep_minutes
+                .store(minutes as usize, Ordering::SeqCst);
+            let _ = tx.send(
+                if pause
+                {
+                    Command::PauseAfterTime
+                }
+                else
+                {
+                    Command::QuitAfterTime
+                },
+            );
+            Ok(())
+        },
+    );
+    // In microseconds like the times of MPRIS; 0 if there's no sleep
+    // timer.
+    b.property("SleepTimer").get(move |_, ()| {
+        let end = config.sleep_end.lock().ok().and_then(|end| *end);
+        Ok(end.map_or(0, |end| {
+            end.saturating_duration_since(Instant::now()).as_micros() as i64
+        }))
+    });
+}
+
+type ComplexReceiver = Receiver<(PathBuf, Option<Metadata>)>;
 
 fn register_interface2(
     b: &mut IfaceBuilder<()>,
@@ -219,11 +329,43 @@ fn register_interface2(
         let _ = tx_clone.send(Command::SkipToPrevious);
         Ok(())
     });
-    let tx_clone = tx;
+    let tx_clone = tx.clone();
     b.method("Play", (), (), move |_, _, _: ()| {
         let _ = tx_clone.send(Command::Resume);
         Ok(())
     });
+    let tx_clone = tx.clone();
+    let config_clone = config.clone();
+    b.method("Seek", ("Offset",), (), move |_, (), (offset,): (i64,)| {
+        seek_to(
+            &tx_clone,
+            &config_clone,
+            current_dbus_time(&config_clone).saturating_add(offset),
+        );
+        Ok(())
+    });
+    let tx_clone = tx.clone();
+    let config_clone = config.clone();
+    b.method(
+        "SetPosition",
+        ("TrackId", "Position"),
+        (),
+        move |_, (), (track_id, pos): (dbus::Path<'static>, i64)| {
+            // Requests for another track are stale and have to be
+            // ignored according to the specification.
+            let current = config_clone
+                .track_id
+                .lock()
+                .ok()
+                .and_then(|track_id| track_id.clone());
+            if pos >= 0 && current.is_none_or(|current| *current == *track_id)
+            {
+                seek_to(&tx_clone, &config_clone, pos);
+            }
+            Ok(())
+        },
+    );
+    b.signal::<(i64,), _>("Seeked", ("Position",));
 
     let tx_control_clone = tx_control.clone();
     let l10n = config.l10n;
@@ -244,34 +386,28 @@ fn register_interface2(
                 },
             )
         });
-    b.property("Rate").get(|_, _| Ok(1.0_f64));
+    register_rate(b, tx, config.clone());
     let config2 = config.clone();
     b.property("Metadata")
         .emits_changed_true()
         .get(move |_, _| Ok(set_metadata(&tx_control.clone(), &rx_path.clone(), &config)));
 
     b.property("Volume").get(|_, _| Ok(1.0_f64));
-    b.property("MaximumRate").get(|_, _| Ok(1.0_f64));
-    b.property("MinimumRate").get(|_, _| Ok(1.0_f64));
     b.property("CanGoNext").get(|_, _| Ok(true));
     b.property("CanGoPrevious").get(|_, _| Ok(true));
     b.property("CanPlay").get(|_, _| Ok(true));
     b.property("CanPause").get(|_, _| Ok(true));
-    b.property("CanSeek").get(|_, _| Ok(false));
+    b.property("CanSeek").get(|_, _| Ok(true));
     b.property("CanControl").get(|_, _| Ok(true));
-    b.property("Position").get(move |_, _| {
-        Ok(to_dbus_time(
-            config2.current_pos.load(Ordering::SeqCst) / config2.channels.load(Ordering::SeqCst),
-            &config2,
-        ))
-    });
+    b.property("Position")
+        .get(move |_, _| Ok(current_dbus_time(&config2)));
 }
 
 pub fn handle_mpris(
     tx: &Sender<Command>,
     tx_control: &Sender<()>,
     rx_paused: Receiver<bool>,
-    rx_path: Receiver<(PathBuf, Option<Tag>)>,
+    rx_path: Receiver<(PathBuf, Option<Metadata>)>,
     config: &Arc<ArcConfig>,
 ) -> Result<(), dbus::Error>
 {
@@ -295,6 +431,12 @@ pub fn handle_mpris(
             register_interface1(b, tx_clone);
         });
 
+        let tx_clone = tx.clone();
+        let config2 = config.clone();
+        let interface3 = cr.register("org.mpris.MediaPlayer2.legacylisten", move |b| {
+            register_legacylisten(b, tx_clone, config2);
+        });
+
         let rx_paused = rx_paused.clone();
         let rx_path = rx_path.clone();
         let config2 = config.clone();
@@ -302,7 +444,11 @@ pub fn handle_mpris(
             register_interface2(b, tx, tx_control, rx_paused, rx_path, config2);
         });
 
-        cr.insert("/org/mpris/MediaPlayer2", &[interface1, interface2], ());
+        cr.insert(
+            "/org/mpris/MediaPlayer2",
+            &[interface1, interface2, interface3],
+            (),
+        );
 
         c.start_receive(
             dbus::message::MatchRule::new_method_call(),
@@ -319,6 +465,18 @@ pub fn handle_mpris(
         while !config.update_dbus.load(Ordering::SeqCst)
         {
             c.process(std::time::Duration::from_millis(10))?;
+
+            if config.seeked.swap(false, Ordering::SeqCst)
+            {
+                let _ = c.send(
+                    dbus::Message::signal(
+                        &"/org/mpris/MediaPlayer2".into(),
+                        &Interface::from("org.mpris.MediaPlayer2.Player"),
+                        &Member::from("Seeked"),
+                    )
+                    .append1(current_dbus_time(config)),
+                );
+            }
         }
         config.update_dbus.store(false, Ordering::SeqCst);
     }
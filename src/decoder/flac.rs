@@ -0,0 +1,86 @@
+use std::{mem, time::Duration};
+
+use claxon::FlacReader;
+
+use crate::{buffer::Buffer, err::Error};
+
+/// Decodes FLAC with its full resolution (rodio cuts everything down
+/// to 16 bit).
+pub struct FlacDecoder
+{
+    reader: FlacReader<Buffer>,
+    // The last decoded block; its channels aren't interleaved.
+    block: Vec<i32>,
+    block_channel_len: usize,
+    pos: usize,
+    // Converts the samples into the range from -1 to 1.
+    scale: f32,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+impl FlacDecoder
+{
+    pub fn new(buffer: Buffer) -> Result<Self, Error>
+    {
+        let reader = FlacReader::new(buffer)?;
+        let info = reader.streaminfo();
+        let channels = u16::try_from(info.channels).unwrap_or(u16::MAX);
+
+        Ok(Self {
+            block: Vec::with_capacity(info.max_block_size as usize * usize::from(channels)),
+            block_channel_len: 1,
+            pos: 0,
+            scale: 1.0 / (1_u64 << (info.bits_per_sample - 1)) as f32,
+            channels,
+            sample_rate: info.sample_rate,
+            // Counted in frames.
+            total_duration: info
+                .samples
+                .map(|frames| Duration::from_secs_f64(frames as f64 / f64::from(info.sample_rate))),
+            reader,
+        })
+    }
+
+    pub const fn channels(&self) -> u16
+    {
+        self.channels
+    }
+
+    pub const fn sample_rate(&self) -> u32
+    {
+        self.sample_rate
+    }
+
+    pub const fn total_duration(&self) -> Option<Duration>
+    {
+        self.total_duration
+    }
+}
+
+impl Iterator for FlacDecoder
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32>
+    {
+        let channels = usize::from(self.channels.max(1));
+
+        while self.pos >= self.block.len()
+        {
+            let buffer = mem::take(&mut self.block);
+            let block = self.reader.blocks().read_next_or_eof(buffer).ok()??;
+
+            self.block_channel_len = block.len() as usize / channels;
+            self.block = block.into_buffer();
+            self.pos = 0;
+        }
+
+        let sample =
+            self.block[(self.pos % channels) * self.block_channel_len + self.pos / channels];
+        self.pos += 1;
+
+        Some(sample as f32 * self.scale)
+    }
+}
@@ -0,0 +1,166 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use rodio::{Decoder, Source};
+
+use crate::{buffer::Buffer, config::ArcConfig, err::Error};
+
+mod flac;
+#[cfg(feature = "symphonia")]
+mod symphonia;
+mod wav;
+
+#[cfg(feature = "symphonia")]
+use self::symphonia::SymphoniaDecoder;
+use self::{flac::FlacDecoder, wav::WavDecoder};
+
+// Files with these extensions are decoded with symphonia right away;
+// rodio couldn't do anything with them anyway.
+#[cfg(feature = "symphonia")]
+const SYMPHONIA_EXTENSIONS: &[&str] = &["opus", "m4a", "m4b", "mp4", "aac"];
+
+// rodio's samples are 16 bit.
+const RODIO_SCALE: f32 = 1.0 / 32768.0;
+
+/// Decodes a song with whatever backend can handle it into samples
+/// from -1 to 1.
+///
+/// FLAC and WAV are decoded with claxon and hound, so that they keep
+/// their resolution, everything else usually with rodio.  With the
+/// `symphonia` feature enabled Opus, AAC/M4A and ALAC are decoded
+/// with symphonia (Opus with ropus as codec, since symphonia has none
+/// for it).
+pub enum SongDecoder
+{
+    Rodio(Box<Decoder<Buffer>>),
+    Flac(Box<FlacDecoder>),
+    Wav(WavDecoder),
+    #[cfg(feature = "symphonia")]
+    Symphonia(SymphoniaDecoder),
+}
+
+impl SongDecoder
+{
+    pub fn new<P: AsRef<Path>>(path: P, config: Arc<ArcConfig>) -> Result<Self, Error>
+    {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+
+        // If the file isn't what its extension says, rodio gets its
+        // chance below.
+        match extension.as_deref()
+        {
+            Some("flac") =>
+            {
+                if let Ok(decoder) = FlacDecoder::new(Buffer::new(&path, config.clone())?)
+                {
+                    return Ok(Self::Flac(Box::new(decoder)));
+                }
+            }
+            Some("wav") =>
+            {
+                if let Ok(decoder) = WavDecoder::new(Buffer::new(&path, config.clone())?)
+                {
+                    return Ok(Self::Wav(decoder));
+                }
+            }
+            _ => (),
+        }
+
+        #[cfg(feature = "symphonia")]
+        {
+            if extension
+                .as_deref()
+                .is_some_and(|ext| SYMPHONIA_EXTENSIONS.contains(&ext))
+            {
+                return Ok(Self::Symphonia(SymphoniaDecoder::new(
+                    Buffer::new(&path, config)?,
+                    extension.as_deref(),
+                )?));
+            }
+
+            // Maybe only the extension is unusual.  rodio's only
+            // error is that it doesn't recognize the format.
+            match Decoder::new(Buffer::new(&path, config.clone())?)
+            {
+                Ok(decoder) => Ok(Self::Rodio(Box::new(decoder))),
+                Err(_) => Ok(Self::Symphonia(SymphoniaDecoder::new(
+                    Buffer::new(&path, config)?,
+                    extension.as_deref(),
+                )?)),
+            }
+        }
+
+        #[cfg(not(feature = "symphonia"))]
+        Ok(Self::Rodio(Box::new(Decoder::new(Buffer::new(
+            path, config,
+        )?)?)))
+    }
+
+    pub fn channels(&self) -> u16
+    {
+        match self
+        {
+            Self::Rodio(decoder) => decoder.channels(),
+            Self::Flac(decoder) => decoder.channels(),
+            Self::Wav(decoder) => decoder.channels(),
+            #[cfg(feature = "symphonia")]
+            Self::Symphonia(decoder) => decoder.channels(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32
+    {
+        match self
+        {
+            Self::Rodio(decoder) => decoder.sample_rate(),
+            Self::Flac(decoder) => decoder.sample_rate(),
+            Self::Wav(decoder) => decoder.sample_rate(),
+            #[cfg(feature = "symphonia")]
+            Self::Symphonia(decoder) => decoder.sample_rate(),
+        }
+    }
+
+    pub fn total_duration(&self) -> Option<Duration>
+    {
+        match self
+        {
+            Self::Rodio(decoder) => decoder.total_duration(),
+            Self::Flac(decoder) => decoder.total_duration(),
+            Self::Wav(decoder) => Some(decoder.total_duration()),
+            #[cfg(feature = "symphonia")]
+            Self::Symphonia(decoder) => decoder.total_duration(),
+        }
+    }
+}
+
+impl Iterator for SongDecoder
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32>
+    {
+        match self
+        {
+            Self::Rodio(decoder) => decoder.next().map(|sample| f32::from(sample) * RODIO_SCALE),
+            Self::Flac(decoder) => decoder.next(),
+            Self::Wav(decoder) => decoder.next(),
+            #[cfg(feature = "symphonia")]
+            Self::Symphonia(decoder) => decoder.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        match self
+        {
+            Self::Rodio(decoder) => decoder.size_hint(),
+            Self::Flac(_) => (0, None),
+            Self::Wav(decoder) => decoder.size_hint(),
+            #[cfg(feature = "symphonia")]
+            Self::Symphonia(_) => (0, None),
+        }
+    }
+}
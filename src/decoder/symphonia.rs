@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use ropus::{Channels, DecodeMode};
+use symphonia::{
+    core::{
+        audio::{self, SampleBuffer},
+        codecs::{self, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS},
+        errors::Error as SymphoniaError,
+        formats::{FormatOptions, FormatReader, Packet},
+        io::{MediaSourceStream, MediaSourceStreamOptions},
+        meta::MetadataOptions,
+        probe::Hint,
+    },
+    default,
+};
+
+use crate::{buffer::Buffer, err::Error};
+
+// Opus is always decoded with this sample rate and a packet contains
+// at most 120 ms of audio.
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+const OPUS_MAX_FRAMES: usize = OPUS_SAMPLE_RATE as usize * 120 / 1000;
+
+enum Codec
+{
+    Symphonia(Box<dyn codecs::Decoder>),
+    Opus(Box<ropus::Decoder>, Vec<f32>),
+}
+
+pub struct SymphoniaDecoder
+{
+    format: Box<dyn FormatReader>,
+    codec: Codec,
+    track_id: u32,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+    // The samples of the last decoded packet and how many of them
+    // were already returned.
+    samples: Vec<f32>,
+    pos: usize,
+}
+
+impl SymphoniaDecoder
+{
+    pub fn new(buffer: Buffer, extension: Option<&str>) -> Result<Self, Error>
+    {
+        let mut hint = Hint::new();
+        if let Some(extension) = extension
+        {
+            hint.with_extension(extension);
+        }
+
+        let format = default::get_probe()
+            .format(
+                &hint,
+                MediaSourceStream::new(Box::new(buffer), MediaSourceStreamOptions::default()),
+                &FormatOptions {
+                    enable_gapless: true,
+                    ..Default::default()
+                },
+                &MetadataOptions::default(),
+            )?
+            .format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+        let params = &track.codec_params;
+        let channels = params.channels.map_or(0, audio::Channels::count);
+
+        let (codec, sample_rate) = if params.codec == CODEC_TYPE_OPUS
+        {
+            let opus_channels = match channels
+            {
+                1 => Channels::Mono,
+                2 => Channels::Stereo,
+                _ =>
+                {
+                    return Err(
+                        SymphoniaError::Unsupported("opus with more than two channels").into(),
+                    )
+                }
+            };
+
+            (
+                Codec::Opus(
+                    Box::new(ropus::Decoder::new(OPUS_SAMPLE_RATE, opus_channels)?),
+                    vec![0.0; OPUS_MAX_FRAMES * channels],
+                ),
+                OPUS_SAMPLE_RATE,
+            )
+        }
+        else
+        {
+            (
+                Codec::Symphonia(default::get_codecs().make(params, &DecoderOptions::default())?),
+                params
+                    .sample_rate
+                    .ok_or(SymphoniaError::Unsupported("unknown sample rate"))?,
+            )
+        };
+
+        let total_duration = params
+            .n_frames
+            .zip(params.time_base)
+            .map(|(frames, time_base)| {
+                let time = time_base.calc_time(frames);
+                Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+            });
+
+        let mut rv = Self {
+            track_id: track.id,
+            channels: channels.try_into().unwrap_or(u16::MAX),
+            sample_rate,
+            total_duration,
+            format,
+            codec,
+            samples: vec![],
+            pos: 0,
+        };
+
+        // Some containers (like MP4) don't tell the number of
+        // channels; then we have to decode the first packet to know
+        // it.
+        if rv.channels == 0 && !rv.fill()
+        {
+            return Err(SymphoniaError::Unsupported("unknown number of channels").into());
+        }
+
+        Ok(rv)
+    }
+
+    pub const fn channels(&self) -> u16
+    {
+        self.channels
+    }
+
+    pub const fn sample_rate(&self) -> u32
+    {
+        self.sample_rate
+    }
+
+    pub const fn total_duration(&self) -> Option<Duration>
+    {
+        self.total_duration
+    }
+
+    // Decodes the packet into `self.samples`, which stays empty if the
+    // packet is broken.
+    fn decode(&mut self, packet: &Packet)
+    {
+        self.samples.clear();
+        self.pos = 0;
+
+        match &mut self.codec
+        {
+            Codec::Symphonia(decoder) =>
+            {
+                let Ok(audio) = decoder.decode(packet)
+                else
+                {
+                    return;
+                };
+
+                let spec = *audio.spec();
+                if self.channels == 0
+                {
+                    self.channels = spec.channels.count().try_into().unwrap_or(u16::MAX);
+                }
+
+                let mut buf = SampleBuffer::new(audio.capacity() as u64, spec);
+                buf.copy_interleaved_ref(audio);
+                self.samples.extend_from_slice(buf.samples());
+            }
+            Codec::Opus(decoder, buf) =>
+            {
+                let Ok(frames) = decoder.decode_float(&packet.data, buf, DecodeMode::Normal)
+                else
+                {
+                    return;
+                };
+
+                self.samples
+                    .extend_from_slice(&buf[..frames * usize::from(self.channels)]);
+            }
+        }
+
+        // Only symphonia's Vorbis decoder (which isn't used here)
+        // removes the encoder delay and padding itself.
+        let channels = usize::from(self.channels);
+        let end = self
+            .samples
+            .len()
+            .saturating_sub(packet.trim_end() as usize * channels);
+        self.samples.truncate(end);
+        let start = (packet.trim_start() as usize * channels).min(self.samples.len());
+        self.samples.drain(..start);
+    }
+
+    // Decodes packets until there are samples left to return.
+    // Returns `false` at the end of the stream.
+    fn fill(&mut self) -> bool
+    {
+        while self.pos >= self.samples.len()
+        {
+            // Errors of the format reader (including the end of the
+            // stream) can't be recovered from, but broken packets can
+            // just be skipped.
+            let Ok(packet) = self.format.next_packet()
+            else
+            {
+                return false;
+            };
+
+            if packet.track_id() == self.track_id
+            {
+                self.decode(&packet);
+            }
+        }
+
+        true
+    }
+}
+
+impl Iterator for SymphoniaDecoder
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32>
+    {
+        if !self.fill()
+        {
+            return None;
+        }
+
+        let sample = self.samples[self.pos];
+        self.pos += 1;
+
+        Some(sample)
+    }
+}
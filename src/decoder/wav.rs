@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use hound::{SampleFormat, WavIntoSamples, WavReader};
+
+use crate::{buffer::Buffer, err::Error};
+
+enum Samples
+{
+    Int(WavIntoSamples<Buffer, i32>, f32),
+    Float(WavIntoSamples<Buffer, f32>),
+}
+
+/// Decodes WAV with its full resolution (rodio cuts everything down
+/// to 16 bit).
+pub struct WavDecoder
+{
+    samples: Samples,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Duration,
+}
+
+impl WavDecoder
+{
+    pub fn new(buffer: Buffer) -> Result<Self, Error>
+    {
+        let reader = WavReader::new(buffer)?;
+        let spec = reader.spec();
+        let total_duration =
+            Duration::from_secs_f64(f64::from(reader.duration()) / f64::from(spec.sample_rate));
+
+        let samples = match spec.sample_format
+        {
+            SampleFormat::Int => Samples::Int(
+                reader.into_samples(),
+                // Converts the samples into the range from -1 to 1.
+                1.0 / (1_u64 << (spec.bits_per_sample - 1)) as f32,
+            ),
+            SampleFormat::Float => Samples::Float(reader.into_samples()),
+        };
+
+        Ok(Self {
+            samples,
+            channels: spec.channels,
+            sample_rate: spec.sample_rate,
+            total_duration,
+        })
+    }
+
+    pub const fn channels(&self) -> u16
+    {
+        self.channels
+    }
+
+    pub const fn sample_rate(&self) -> u32
+    {
+        self.sample_rate
+    }
+
+    pub const fn total_duration(&self) -> Duration
+    {
+        self.total_duration
+    }
+}
+
+impl Iterator for WavDecoder
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32>
+    {
+        // A broken sample can't be skipped, so it's the end.
+        match &mut self.samples
+        {
+            Samples::Int(samples, scale) => Some(samples.next()?.ok()? as f32 * *scale),
+            Samples::Float(samples) => samples.next()?.ok(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        match &self.samples
+        {
+            Samples::Int(samples, _) => samples.size_hint(),
+            Samples::Float(samples) => samples.size_hint(),
+        }
+    }
+}
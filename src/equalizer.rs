@@ -0,0 +1,258 @@
+use std::{f32::consts::PI, path::Path, sync::Arc};
+
+use crate::{config::ArcConfig, csv::Csv, songs::Song};
+
+/// The name of the preset that changes nothing; it always exists.
+pub const FLAT: &str = "flat";
+
+// Q of a band if none is given; the same as a Butterworth filter.
+const DEFAULT_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BandKind
+{
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Band
+{
+    kind: BandKind,
+    frequency: f32,
+    gain_db: f32,
+    q: f32,
+}
+
+/// A named set of filters from `equalizer.csv`.
+#[derive(Debug)]
+pub struct Preset
+{
+    pub name: String,
+    bands: Vec<Band>,
+}
+
+// Reads a band from a line of `equalizer.csv`; wrong lines are
+// ignored like in the conffile.
+fn parse_band(line: &[String]) -> Option<Band>
+{
+    if !(4..=5).contains(&line.len())
+    {
+        return None;
+    }
+
+    let kind = match line[1].as_str()
+    {
+        "peak" => BandKind::Peak,
+        "lowshelf" => BandKind::LowShelf,
+        "highshelf" => BandKind::HighShelf,
+        _ => return None,
+    };
+    let q = match line.get(4)
+    {
+        Some(q) => q.parse().ok()?,
+        None => DEFAULT_Q,
+    };
+
+    Some(Band {
+        kind,
+        frequency: line[2].parse().ok()?,
+        gain_db: line[3].parse().ok()?,
+        q,
+    })
+}
+
+/// Reads the presets from `equalizer.csv` in the config directory.
+/// Every line is one band of the preset it names, so a preset
+/// consists of all lines with its name.  `flat` is always first.
+pub fn read_presets(config_dir: &Path) -> Vec<Arc<Preset>>
+{
+    let mut presets = vec![Preset {
+        name: String::from(FLAT),
+        bands: vec![],
+    }];
+
+    // The file is optional.
+    let entries =
+        Csv::new(config_dir.join("equalizer.csv")).map_or_else(|_| vec![], |csv| csv.entries);
+    for line in entries
+    {
+        let Some(band) = parse_band(&line)
+        else
+        {
+            continue;
+        };
+
+        if let Some(preset) = presets.iter_mut().find(|preset| preset.name == line[0])
+        {
+            preset.bands.push(band);
+        }
+        else
+        {
+            presets.push(Preset {
+                name: line[0].clone(),
+                bands: vec![band],
+            });
+        }
+    }
+
+    presets.into_iter().map(Arc::new).collect()
+}
+
+/// Looks up the preset with that name or the default one if it's
+/// `None`.
+pub fn find_preset(name: Option<&str>, config: &ArcConfig) -> Option<Arc<Preset>>
+{
+    let name = name.or(config.conffile.equalizer.as_deref())?;
+
+    config
+        .presets
+        .iter()
+        .find(|preset| preset.name == name)
+        .cloned()
+}
+
+// A second order IIR filter (transposed direct form II) with the
+// coefficients from the "Audio EQ Cookbook" by Robert
+// Bristow-Johnson.
+#[derive(Clone, Copy, Debug)]
+struct Biquad
+{
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+// `mul_add` is really slow on CPUs without FMA and this is run for
+// every sample.
+#[allow(clippy::suboptimal_flops)]
+impl Biquad
+{
+    fn new(band: Band, sample_rate: u32) -> Self
+    {
+        let a = 10.0_f32.powf(band.gain_db / 40.0);
+        // Frequencies above Nyquist would make the filter unstable.
+        let frequency = band.frequency.clamp(1.0, sample_rate as f32 * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(0.01));
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind
+        {
+            BandKind::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandKind::LowShelf =>
+            {
+                let root = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + root),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - root),
+                    (a + 1.0) + (a - 1.0) * cos + root,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - root,
+                )
+            }
+            BandKind::HighShelf =>
+            {
+                let root = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + root),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - root),
+                    (a + 1.0) - (a - 1.0) * cos + root,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - root,
+                )
+            }
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32
+    {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+
+        y
+    }
+}
+
+/// The filters of a preset for one song; every channel has its own
+/// ones.
+pub struct Equalizer
+{
+    channels: usize,
+    filters: Vec<Biquad>,
+}
+
+impl Equalizer
+{
+    /// `None` if the preset doesn't change anything.
+    pub fn new(preset: &Preset, sample_rate: u32, channels: u16) -> Option<Self>
+    {
+        if preset.bands.is_empty()
+        {
+            return None;
+        }
+
+        let channels = usize::from(channels).max(1);
+
+        Some(Self {
+            channels,
+            filters: (0..channels)
+                .flat_map(|_| {
+                    preset
+                        .bands
+                        .iter()
+                        .map(|&band| Biquad::new(band, sample_rate))
+                })
+                .collect(),
+        })
+    }
+
+    /// Filters the `i`th sample of the song.
+    pub fn process(&mut self, i: usize, sample: f32) -> f32
+    {
+        let bands = self.filters.len() / self.channels;
+        let channel = i % self.channels;
+
+        // Boosting can exceed the range, but it's only clipped at the
+        // very end by the output.
+        self.filters[channel * bands..(channel + 1) * bands]
+            .iter_mut()
+            .fold(sample, |x, filter| filter.process(x))
+    }
+}
+
+impl Song
+{
+    /// The preset this song is played with: its own one or the
+    /// default from the conffile.  `None` if it doesn't exist.
+    #[must_use]
+    pub fn preset(&self, config: &ArcConfig) -> Option<Arc<Preset>>
+    {
+        find_preset(self.equalizer.as_deref(), config)
+    }
+}
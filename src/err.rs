@@ -31,6 +31,17 @@ pub enum Error
     Recv(RecvError),
     Walkdir(walkdir::Error),
     StripPrefixError(StripPrefixError),
+    Loudness(ebur128::Error),
+    Wav(hound::Error),
+    Flac(claxon::Error),
+    Tag(lofty::error::LoftyError),
+    Id3(id3::Error),
+    /// Submitting listens failed.
+    Http(Box<ureq::Error>),
+    #[cfg(feature = "symphonia")]
+    Symphonia(symphonia::core::errors::Error),
+    #[cfg(feature = "symphonia")]
+    Opus(ropus::DecoderInitError),
     Custom(String),
     Vec(Vec<Error>),
 }
@@ -55,6 +66,16 @@ impl fmt::Display for Error
             Self::Recv(err) => write!(f, "Recv error: {}", err),
             Self::Walkdir(err) => write!(f, "Walkdir error: {}", err),
             Self::StripPrefixError(err) => write!(f, "Strip prefix error: {}", err),
+            Self::Loudness(err) => write!(f, "Loudness measurement error: {err}"),
+            Self::Wav(err) => write!(f, "WAV error: {err}"),
+            Self::Flac(err) => write!(f, "FLAC error: {err}"),
+            Self::Tag(err) => write!(f, "Tag error: {err}"),
+            Self::Id3(err) => write!(f, "ID3 error: {err}"),
+            Self::Http(err) => write!(f, "HTTP error: {err}"),
+            #[cfg(feature = "symphonia")]
+            Self::Symphonia(err) => write!(f, "Symphonia error: {err}"),
+            #[cfg(feature = "symphonia")]
+            Self::Opus(err) => write!(f, "Opus error: {err}"),
             Self::Custom(err) => write!(f, "Custom error: {}", err),
             Self::Vec(v) =>
             {
@@ -187,6 +208,72 @@ impl From<StripPrefixError> for Error
     }
 }
 
+impl From<ebur128::Error> for Error
+{
+    fn from(err: ebur128::Error) -> Self
+    {
+        Self::Loudness(err)
+    }
+}
+
+impl From<hound::Error> for Error
+{
+    fn from(err: hound::Error) -> Self
+    {
+        Self::Wav(err)
+    }
+}
+
+impl From<claxon::Error> for Error
+{
+    fn from(err: claxon::Error) -> Self
+    {
+        Self::Flac(err)
+    }
+}
+
+impl From<lofty::error::LoftyError> for Error
+{
+    fn from(err: lofty::error::LoftyError) -> Self
+    {
+        Self::Tag(err)
+    }
+}
+
+impl From<id3::Error> for Error
+{
+    fn from(err: id3::Error) -> Self
+    {
+        Self::Id3(err)
+    }
+}
+
+impl From<ureq::Error> for Error
+{
+    fn from(err: ureq::Error) -> Self
+    {
+        Self::Http(Box::new(err))
+    }
+}
+
+#[cfg(feature = "symphonia")]
+impl From<symphonia::core::errors::Error> for Error
+{
+    fn from(err: symphonia::core::errors::Error) -> Self
+    {
+        Self::Symphonia(err)
+    }
+}
+
+#[cfg(feature = "symphonia")]
+impl From<ropus::DecoderInitError> for Error
+{
+    fn from(err: ropus::DecoderInitError) -> Self
+    {
+        Self::Opus(err)
+    }
+}
+
 impl From<String> for Error
 {
     fn from(err: String) -> Self
@@ -23,28 +23,6 @@ pub fn recv_last<T>(rx: &Receiver<T>) -> T
     }
 }
 
-pub fn take_error<T: Clone, E>(x: Result<T, E>) -> (Result<T, E>, Option<T>)
-{
-    match x
-    {
-        Ok(v) => (Ok(v.clone()), Some(v)),
-        Err(e) => (Err(e), None),
-    }
-}
-
-pub fn unwrap_two<T, U, F>(x: Option<T>, y: Option<U>, f: F) -> (T, U)
-where
-    F: FnOnce() -> (T, U),
-{
-    match (x, y)
-    {
-        (Some(x), Some(y)) => (x, y),
-        (Some(x), None) => (x, f().1),
-        (None, Some(y)) => (f().0, y),
-        (None, None) => f(),
-    }
-}
-
 pub trait ResultExtend
 {
     type Inner;
@@ -0,0 +1,150 @@
+use std::{
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::Ordering,
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use walkdir::WalkDir;
+
+use crate::config::{ArcConfig, Config};
+
+/// The environment variables a hook gets.
+pub type HookEnv = Vec<(&'static str, String)>;
+
+/// What the hooks are run for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event
+{
+    SongStarted,
+    SongFinished,
+    Skipped,
+    Paused,
+    Resumed,
+    LikelihoodChanged,
+    VolumeChanged,
+}
+
+impl Event
+{
+    const fn name(self) -> &'static str
+    {
+        match self
+        {
+            Self::SongStarted => "song-started",
+            Self::SongFinished => "song-finished",
+            Self::Skipped => "skipped",
+            Self::Paused => "paused",
+            Self::Resumed => "resumed",
+            Self::LikelihoodChanged => "likelihood-changed",
+            Self::VolumeChanged => "volume-changed",
+        }
+    }
+}
+
+// Starts every hook for every event in the order the events happened,
+// but doesn't wait for them to finish.
+fn run_hooks(rx: &Receiver<HookEnv>, dir: &Path)
+{
+    let mut running: Vec<Child> = vec![];
+
+    loop
+    {
+        // So that finished hooks don't stay zombies until the next event,
+        // which may take very long when paused.
+        running.retain_mut(|child| !matches!(child.try_wait(), Ok(Some(_))));
+        let env = match rx.recv_timeout(Duration::from_secs(1))
+        {
+            Ok(env) => env,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        // The order of the file names, so that it's always the same.
+        for entry in WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| !entry.file_type().is_dir())
+        {
+            // Files which aren't executable are just ignored.
+            if let Ok(child) = Command::new(entry.path())
+                .envs(env.iter().map(|(key, value)| (*key, value)))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .spawn()
+            {
+                running.push(child);
+            }
+        }
+    }
+}
+
+/// Starts the thread which runs the scripts in the `hooks` directory.
+pub fn start_hooks(config: &ArcConfig) -> Sender<HookEnv>
+{
+    let (tx, rx) = channel();
+    let dir = config.config_dir.join("hooks");
+
+    thread::spawn(move || run_hooks(&rx, &dir));
+
+    tx
+}
+
+impl Config
+{
+    /// Tells the hooks that something happened.  Besides the event
+    /// they get everything that is known about the current song.
+    pub fn run_hooks(&self, event: Event)
+    {
+        let mut env = vec![
+            ("LEGACYLISTEN_EVENT", event.name().to_string()),
+            ("LEGACYLISTEN_LIKELIHOOD", self.num.to_string()),
+            (
+                "LEGACYLISTEN_VOLUME",
+                (self.loud * 100.0).round().to_string(),
+            ),
+            ("LEGACYLISTEN_PAUSED", self.paused.to_string()),
+        ];
+
+        if let Some(path) = &self.song_path
+        {
+            env.push(("LEGACYLISTEN_SONG", path.to_string_lossy().into_owned()));
+        }
+        if let Some(Ok(metadata)) = &self.metadata
+        {
+            let strings = [
+                ("LEGACYLISTEN_TITLE", &metadata.title),
+                ("LEGACYLISTEN_ARTIST", &metadata.artist),
+                ("LEGACYLISTEN_ALBUM", &metadata.album),
+            ];
+            for (key, value) in strings
+            {
+                if let Some(value) = value
+                {
+                    env.push((key, value.clone()));
+                }
+            }
+        }
+        // At the start and end of a song the position is (at least
+        // partly) the one of the song before or after.
+        let between = !matches!(event, Event::SongStarted | Event::SongFinished);
+        if let Some(pos) = self
+            .source
+            .pos_to_duration(self.arc_config.current_pos.load(Ordering::SeqCst))
+            .filter(|_| between && self.source.started())
+        {
+            env.push(("LEGACYLISTEN_POSITION", pos.as_secs_f64().to_string()));
+        }
+        if let Some(duration) = self.source.duration()
+        {
+            env.push(("LEGACYLISTEN_DURATION", duration.as_secs_f64().to_string()));
+        }
+
+        let _ = self.tx_hooks.send(env);
+    }
+}
@@ -29,7 +29,9 @@ sync-lyrics = Synchronized lyrics: { $text }
 comment = Comment: { $text }
 num-pictures = Pictures #: { $text }
 ext-links = ————Extended Links————
+ext-link = { $description }: { $link }
 ext-texts = ————Extended Texts————
+ext-text = { $description }: { $text }
 metadata-unsupported = Metadata is not supported for this song: { $err }
 print-info-unreachable = this shouldn't be reached
 max-likelihood-reached = Maximal playing likelihood already reached
@@ -80,6 +82,27 @@ disable-repeat = Disable repeating of current song
 repeat-once = Repeat current song once
 repeat-forever = Repeat current song forever
 skip-to-previous = Skip to previous song
+seek-forward = Seek forward by 10 seconds
+seek-backward = Seek backward by 10 seconds
+seek-forward-far = Seek forward by a minute
+seek-backward-far = Seek backward by a minute
+seek-to = Seek to the second typed directly before this command (or to the beginning)
+increase-rate = Increase playback rate by 10%
+decrease-rate = Decrease playback rate by 10%
+set-rate = Play at the speed (in percent) typed directly before this command (or at normal speed)
+increase-song-rate = Increase playback rate of current song permanently by 10%
+decrease-song-rate = Decrease playback rate of current song permanently by 10%
+cycle-equalizer = Switch current song permanently to the next equalizer preset
+start-here = Let current song permanently start at the current position
+end-here = Let current song permanently end at the current position (and end it now)
+play-completely = Play current song permanently from the beginning to the end again
+quit-after-time = Quit {-name} after the minutes typed directly before this command, fading out over the last minute (or cancel that)
+pause-after-time = Pause playing after the minutes typed directly before this command, fading out over the last minute (or cancel that)
+loop-start = Mark the start of a loop in the current song
+loop-end = Mark the end of the loop and play the part from its start to here again and again (until repeating is disabled)
+toggle-remember = Toggle whether the position in the current song is remembered, so that it goes on there the next time
+edit-tags = Edit the title, artist, album, track and year of the current song
+toggle-lyrics = Toggle showing the synchronized lyrics line by line while they are sung
 help-header = Press the corresponding letter of the command that you want to execute:
 command-reading-problem = problem with reading commands; ending …
 reading-song-problem = Problem reading song { $path }: { $err }
@@ -98,5 +121,57 @@ controller-out = Controller channel out of scope; this is a bug, please report i
 too-many-tries = Too many unsuccessful tries to read a song were made; probably all songs are unplayable; ending …
 positive-bonus = Increased likelihood of this repeating song to { $bonus }
 negative-bonus = Decreased likelihood of this repeating song to { $bonus }
+seeking-to = seeking to { $pos }s
+loudness-measured = measured loudness of { $song }: { $loudness } LUFS
+proposed-volume = normalized volume would be { $loud }%
+playing-at-rate = playing at { $rate }% speed
+song-rate = playing this song permanently at { $rate }% speed
+real-time-left = playing at { $rate }% speed, so it ends in { $left }s
+equalizer = playing this song permanently with equalizer preset { $preset }
+equalizer-default = playing this song with the default equalizer preset ({ $preset })
+starting-at = the song now starts at { $pos }s
+ending-at = the song now ends at { $pos }s
+playing-completely = the song is played completely again
+silence-detected = skipping silence: playing from { $start }s to { $end }s
+output-device-not-found = output device { $device } not found; using the default one
+no-output-device = no usable output device ({ $err }); the sound is discarded
+wav-output-error = can't write into the WAV file anymore: { $err }
+no-new-sink = can't open the output device again ({ $err }); cutting without fading
+quitting-in = quitting in { $left }s
+pausing-in = pausing in { $left }s
+sleep-timer-cancelled = sleep timer cancelled
+no-sleep-timer = There is no sleep timer to cancel
+alarm-waiting = waiting for the alarm at { $time } (in { $left } minutes); till then only quitting is possible
+alarm-going-off = good morning!
+invalid-alarm = invalid alarm time { $time } (it has to be like 7:30)
+loop-start-marked = the loop starts at { $pos }s
+looping = looping from { $start }s to { $end }s
+no-loop-start = There is no start of a loop before the current position
+loop-too-long = Parts longer than five minutes can't be looped
+loop-cleared = stopped looping
+remembering-position = the position in this song is remembered
+not-remembering-position = the position in this song isn't remembered anymore
+resuming-at = going on at { $pos }s
+session-not-saved = couldn't save which songs were played: { $err }
+edit-title = New title (empty keeps the old one):
+edit-artist = New artist (empty keeps the old one):
+edit-album = New album (empty keeps the old one):
+edit-track = New track number (empty keeps the old one):
+edit-year = New year (empty keeps the old one):
+not-a-number = { $text } isn't a number; try again:
+tags-unchanged = nothing was changed
+song-changed-while-editing = another song started while editing; the tags weren't changed
+tags-saved = tags saved
+tags-not-saved = couldn't save the tags: { $err }
+showing-lyrics = showing the synchronized lyrics
+not-showing-lyrics = not showing the synchronized lyrics anymore
+no-sync-lyrics = this song has no synchronized lyrics
+lyrics-line = { $text }
+library-not-saved = couldn't save the library index: { $err }
+parser-timed-out = the parser plugin { $plugin } didn't answer in time
+parser-unsupported = the parser plugin { $plugin } needs a newer version of legacylisten
+listens-not-saved = couldn't save the listens for ListenBrainz: { $err }
+submitting-listens-failed = couldn't submit the listens to ListenBrainz (trying again later): { $err }
+listen-dropped = the listen of { $filename } can't be submitted to ListenBrainz and is dropped
 ";
 pub static ENGLISH_L10N_LANG_ID: &str = "en-US";
@@ -30,7 +30,9 @@ sync-lyrics = Synchronisierter Liedtext: { $text }
 comment = Kommentar: { $text }
 num-pictures = Bilder #: { $text }
 ext-links = ————Extended Links————
+ext-link = { $description }: { $link }
 ext-texts = ————Extended Texts————
+ext-text = { $description }: { $text }
 metadata-unsupported = Metadaten sind nicht unterstützt in diesem Lied: { $err }
 print-info-unreachable = Das sollte nicht erreicht werden
 max-likelihood-reached = Maximale {-likelihood} ist schon erreicht
@@ -81,6 +83,27 @@ disable-repeat = Wiederholen des aktuellen Lieds beenden
 repeat-once = Diese Lied einmal wiederholen
 repeat-forever = Diese Lied ewig wiederholen
 skip-to-previous = Zum vorherigen Lied springen
+seek-forward = 10 Sekunden vorspulen
+seek-backward = 10 Sekunden zurückspulen
+seek-forward-far = Eine Minute vorspulen
+seek-backward-far = Eine Minute zurückspulen
+seek-to = Zu der direkt vor diesem Befehl getippten Sekunde springen (oder zum Anfang)
+increase-rate = Abspielgeschwindigkeit um 10% erhöhen
+decrease-rate = Abspielgeschwindigkeit um 10% verringern
+set-rate = Mit der direkt vor diesem Befehl getippten Geschwindigkeit (in Prozent) abspielen (oder mit normaler Geschwindigkeit)
+increase-song-rate = Abspielgeschwindigkeit des aktuellen Lieds permanent um 10% erhöhen
+decrease-song-rate = Abspielgeschwindigkeit des aktuellen Lieds permanent um 10% verringern
+cycle-equalizer = Aktuelles Lied permanent zur nächsten Equalizer-Voreinstellung wechseln
+start-here = Aktuelles Lied permanent an der aktuellen Position beginnen lassen
+end-here = Aktuelles Lied permanent an der aktuellen Position enden lassen (und es jetzt beenden)
+play-completely = Aktuelles Lied permanent wieder vom Anfang bis zum Ende abspielen
+quit-after-time = Das Programm nach den direkt vor diesem Befehl getippten Minuten beenden und dabei in der letzten Minute ausblenden (oder das abbrechen)
+pause-after-time = Nach den direkt vor diesem Befehl getippten Minuten pausieren und dabei in der letzten Minute ausblenden (oder das abbrechen)
+loop-start = Den Anfang einer Schleife im aktuellen Lied markieren
+loop-end = Das Ende der Schleife markieren und den Teil von ihrem Anfang bis hier immer wieder abspielen (bis das Wiederholen abgeschaltet wird)
+toggle-remember = Umschalten, ob die Position im aktuellen Lied gespeichert wird, damit es das nächste Mal dort weitergeht
+edit-tags = Titel, Künstler, Album, Tracknummer und Jahr des aktuellen Lieds bearbeiten
+toggle-lyrics = Umschalten, ob der synchronisierte Liedtext Zeile für Zeile angezeigt wird, während er gesungen wird
 help-header = Drücke die Taste, die zu dem Befehl gehört, der ausgeführt werden soll:
 command-reading-problem = Problem mit dem Lesen von Befehlen; stoppe …
 reading-song-problem = Problem das Lied { $path } zu lesen: { $err }
@@ -99,5 +122,57 @@ controller-out = Controllerkanal ist out of scope; das ist ein Bug, bitte melde
 too-many-tries = Zu viele erfolglose Versuche ein Lied zu lesen; wahrscheinlich sind alle Lieder unspielbar; stoppe …
 positive-bonus = {-likelihood} wegen Wiederholung auf { $bonus } erhöht
 negative-bonus = {-likelihood} wegen Wiederholung auf { $bonus } vermindert
+seeking-to = springe zu { $pos }s
+loudness-measured = Gemessene Lautheit von { $song }: { $loudness } LUFS
+proposed-volume = Normalisierte Lautstärke wäre { $loud }%
+playing-at-rate = Spiele mit { $rate }% Geschwindigkeit ab
+song-rate = Spiele dieses Lied permanent mit { $rate }% Geschwindigkeit ab
+real-time-left = Spiele mit { $rate }% Geschwindigkeit ab, daher endet es in { $left }s
+equalizer = Spiele dieses Lied permanent mit der Equalizer-Voreinstellung { $preset } ab
+equalizer-default = Spiele dieses Lied mit der Standard-Equalizer-Voreinstellung ({ $preset }) ab
+starting-at = Das Lied beginnt jetzt bei { $pos }s
+ending-at = Das Lied endet jetzt bei { $pos }s
+playing-completely = Das Lied wird wieder vollständig abgespielt
+silence-detected = Überspringe Stille: Spiele von { $start }s bis { $end }s
+output-device-not-found = Ausgabegerät { $device } nicht gefunden; benutze das Standardgerät
+no-output-device = Kein benutzbares Ausgabegerät ({ $err }); der Ton wird verworfen
+wav-output-error = Kann nicht mehr in die WAV-Datei schreiben: { $err }
+no-new-sink = Kann das Ausgabegerät nicht nochmal öffnen ({ $err }); es wird ohne Überblenden geschnitten
+quitting-in = Beende in { $left }s
+pausing-in = Pausiere in { $left }s
+sleep-timer-cancelled = Schlaftimer abgebrochen
+no-sleep-timer = Es gibt keinen Schlaftimer zum Abbrechen
+alarm-waiting = Warte auf den Wecker um { $time } (in { $left } Minuten); bis dahin ist nur Beenden möglich
+alarm-going-off = Guten Morgen!
+invalid-alarm = Ungültige Weckzeit { $time } (sie muss wie 7:30 sein)
+loop-start-marked = Die Schleife beginnt bei { $pos }s
+looping = Wiederhole von { $start }s bis { $end }s
+no-loop-start = Es gibt keinen Anfang einer Schleife vor der aktuellen Position
+loop-too-long = Teile, die länger als fünf Minuten sind, können nicht wiederholt werden
+loop-cleared = Schleife beendet
+remembering-position = Die Position in diesem Lied wird gespeichert
+not-remembering-position = Die Position in diesem Lied wird nicht mehr gespeichert
+resuming-at = Mache bei { $pos }s weiter
+session-not-saved = Konnte nicht speichern, welche Lieder gespielt wurden: { $err }
+edit-title = Neuer Titel (leer behält den alten):
+edit-artist = Neuer Künstler (leer behält den alten):
+edit-album = Neues Album (leer behält das alte):
+edit-track = Neue Tracknummer (leer behält die alte):
+edit-year = Neues Jahr (leer behält das alte):
+not-a-number = { $text } ist keine Zahl; versuche es nochmal:
+tags-unchanged = Es wurde nichts geändert
+song-changed-while-editing = Während des Bearbeitens hat ein anderes Lied angefangen; die Tags wurden nicht geändert
+tags-saved = Tags gespeichert
+tags-not-saved = Konnte die Tags nicht speichern: { $err }
+showing-lyrics = Zeige den synchronisierten Liedtext an
+not-showing-lyrics = Zeige den synchronisierten Liedtext nicht mehr an
+no-sync-lyrics = Dieses Lied hat keinen synchronisierten Liedtext
+lyrics-line = { $text }
+library-not-saved = Konnte den Index der Bibliothek nicht speichern: { $err }
+parser-timed-out = Das Parser-Plugin { $plugin } hat nicht rechtzeitig geantwortet
+parser-unsupported = Das Parser-Plugin { $plugin } braucht eine neuere Version von legacylisten
+listens-not-saved = Konnte die Listens für ListenBrainz nicht speichern: { $err }
+submitting-listens-failed = Konnte die Listens nicht an ListenBrainz senden (wird später wieder versucht): { $err }
+listen-dropped = Der Listen von { $filename } kann nicht an ListenBrainz gesendet werden und wird verworfen
 ";
 pub static GERMAN_L10N_LANG_ID: &str = "de-DE";
@@ -1,6 +1,5 @@
 use either::Either;
 use fluent::types::FluentNumber;
-use id3::frame::{Comment, Lyrics};
 use Either::{Left, Right};
 
 use crate::{commands::Command, Error};
@@ -45,12 +44,14 @@ pub enum Message<'a>
     Track(String),
     TracksTotal(String),
     Duration(String),
-    Lyrics(&'a Lyrics),
+    Lyrics(String),
     SyncLyrics(String),
-    Comment(&'a Comment),
+    Comment(String),
     NumPictures(usize),
     ExtLinks,
+    ExtLink(String, String),
     ExtTexts,
+    ExtText(String, String),
     MetadataUnsupported(String),
     PrintInfoUnreachable,
     MaxLikelihoodReached,
@@ -102,6 +103,58 @@ pub enum Message<'a>
     Description(Command),
     PositiveBonus(u32),
     NegativeBonus(u32),
+    SeekingTo(f64),
+    LoudnessMeasured(String, f64),
+    ProposedVolume(f64),
+    PlayingAtRate(f64),
+    SongRate(f64),
+    RealTimeLeft(f64, f64),
+    Equalizer(String),
+    EqualizerDefault(String),
+    StartingAt(f64),
+    EndingAt(f64),
+    PlayingCompletely,
+    SilenceDetected(f64, f64),
+    OutputDeviceNotFound(String),
+    NoOutputDevice(String),
+    WavOutputError(String),
+    NoNewSink(String),
+    QuittingIn(f64),
+    PausingIn(f64),
+    SleepTimerCancelled,
+    NoSleepTimer,
+    AlarmWaiting(String, f64),
+    AlarmGoingOff,
+    InvalidAlarm(String),
+    LoopStartMarked(f64),
+    Looping(f64, f64),
+    NoLoopStart,
+    LoopTooLong,
+    LoopCleared,
+    RememberingPosition,
+    NotRememberingPosition,
+    ResumingAt(f64),
+    SessionNotSaved(String),
+    EditTitle,
+    EditArtist,
+    EditAlbum,
+    EditTrack,
+    EditYear,
+    NotANumber(String),
+    TagsUnchanged,
+    SongChangedWhileEditing,
+    TagsSaved,
+    TagsNotSaved(String),
+    ShowingLyrics,
+    NotShowingLyrics,
+    NoSyncLyrics,
+    LyricsLine(String),
+    LibraryNotSaved(String),
+    ParserTimedOut(String),
+    ParserUnsupported(String),
+    ListensNotSaved(String),
+    SubmittingListensFailed(String),
+    ListenDropped(String),
 }
 
 impl Message<'_>
@@ -142,7 +195,9 @@ impl Message<'_>
             Self::Comment(_) => "comment",
             Self::NumPictures(_) => "num-pictures",
             Self::ExtLinks => "ext-links",
+            Self::ExtLink(_, _) => "ext-link",
             Self::ExtTexts => "ext-texts",
+            Self::ExtText(_, _) => "ext-text",
             Self::MetadataUnsupported(_) => "metadata-unsupported",
             Self::PrintInfoUnreachable => "print-info-unreachable",
             Self::MaxLikelihoodReached => "max-likelihood-reached",
@@ -209,11 +264,86 @@ impl Message<'_>
             Self::Description(Command::RepeatOnce) => "repeat-once",
             Self::Description(Command::RepeatForever) => "repeat-forever",
             Self::Description(Command::SkipToPrevious) => "skip-to-previous",
+            Self::Description(Command::SeekForward) => "seek-forward",
+            Self::Description(Command::SeekBackward) => "seek-backward",
+            Self::Description(Command::SeekForwardFar) => "seek-forward-far",
+            Self::Description(Command::SeekBackwardFar) => "seek-backward-far",
+            Self::Description(Command::SeekTo) => "seek-to",
+            Self::Description(Command::IncreaseRate) => "increase-rate",
+            Self::Description(Command::DecreaseRate) => "decrease-rate",
+            Self::Description(Command::SetRate) => "set-rate",
+            Self::Description(Command::IncreaseSongRate) => "increase-song-rate",
+            Self::Description(Command::DecreaseSongRate) => "decrease-song-rate",
+            Self::Description(Command::CycleEqualizer) => "cycle-equalizer",
+            Self::Description(Command::StartHere) => "start-here",
+            Self::Description(Command::EndHere) => "end-here",
+            Self::Description(Command::PlayCompletely) => "play-completely",
+            Self::Description(Command::QuitAfterTime) => "quit-after-time",
+            Self::Description(Command::PauseAfterTime) => "pause-after-time",
+            Self::Description(Command::LoopStart) => "loop-start",
+            Self::Description(Command::LoopEnd) => "loop-end",
+            Self::Description(Command::ToggleRemember) => "toggle-remember",
+            Self::Description(Command::EditTags) => "edit-tags",
+            Self::Description(Command::ToggleLyrics) => "toggle-lyrics",
             Self::PositiveBonus(_) => "positive-bonus",
             Self::NegativeBonus(_) => "negative-bonus",
+            Self::SeekingTo(_) => "seeking-to",
+            Self::LoudnessMeasured(_, _) => "loudness-measured",
+            Self::ProposedVolume(_) => "proposed-volume",
+            Self::PlayingAtRate(_) => "playing-at-rate",
+            Self::SongRate(_) => "song-rate",
+            Self::RealTimeLeft(_, _) => "real-time-left",
+            Self::Equalizer(_) => "equalizer",
+            Self::EqualizerDefault(_) => "equalizer-default",
+            Self::StartingAt(_) => "starting-at",
+            Self::EndingAt(_) => "ending-at",
+            Self::PlayingCompletely => "playing-completely",
+            Self::SilenceDetected(_, _) => "silence-detected",
+            Self::OutputDeviceNotFound(_) => "output-device-not-found",
+            Self::NoOutputDevice(_) => "no-output-device",
+            Self::WavOutputError(_) => "wav-output-error",
+            Self::NoNewSink(_) => "no-new-sink",
+            Self::QuittingIn(_) => "quitting-in",
+            Self::PausingIn(_) => "pausing-in",
+            Self::SleepTimerCancelled => "sleep-timer-cancelled",
+            Self::NoSleepTimer => "no-sleep-timer",
+            Self::AlarmWaiting(_, _) => "alarm-waiting",
+            Self::AlarmGoingOff => "alarm-going-off",
+            Self::InvalidAlarm(_) => "invalid-alarm",
+            Self::LoopStartMarked(_) => "loop-start-marked",
+            Self::Looping(_, _) => "looping",
+            Self::NoLoopStart => "no-loop-start",
+            Self::LoopTooLong => "loop-too-long",
+            Self::LoopCleared => "loop-cleared",
+            Self::RememberingPosition => "remembering-position",
+            Self::NotRememberingPosition => "not-remembering-position",
+            Self::ResumingAt(_) => "resuming-at",
+            Self::SessionNotSaved(_) => "session-not-saved",
+            Self::EditTitle => "edit-title",
+            Self::EditArtist => "edit-artist",
+            Self::EditAlbum => "edit-album",
+            Self::EditTrack => "edit-track",
+            Self::EditYear => "edit-year",
+            Self::NotANumber(_) => "not-a-number",
+            Self::TagsUnchanged => "tags-unchanged",
+            Self::SongChangedWhileEditing => "song-changed-while-editing",
+            Self::TagsSaved => "tags-saved",
+            Self::TagsNotSaved(_) => "tags-not-saved",
+            Self::ShowingLyrics => "showing-lyrics",
+            Self::NotShowingLyrics => "not-showing-lyrics",
+            Self::NoSyncLyrics => "no-sync-lyrics",
+            Self::LyricsLine(_) => "lyrics-line",
+            Self::LibraryNotSaved(_) => "library-not-saved",
+            Self::ParserTimedOut(_) => "parser-timed-out",
+            Self::ParserUnsupported(_) => "parser-unsupported",
+            Self::ListensNotSaved(_) => "listens-not-saved",
+            Self::SubmittingListensFailed(_) => "submitting-listens-failed",
+            Self::ListenDropped(_) => "listen-dropped",
         }
     }
 
+    // See above.
+    #[allow(clippy::too_many_lines)]
     pub fn into_vec(self) -> Vec<(&'static str, Either<String, FluentNumber>)>
     {
         match self
@@ -223,7 +353,19 @@ impl Message<'_>
             Self::UnknownCommandByte(b) => vec![("byte", Left(b.to_string()))],
             Self::InSignalHandler(sig) => vec![("sig", Left(sig.to_string()))],
             Self::MprisHandlerError(err) => vec![("err", Left(format!("{:?}", err)))],
-            Self::NewSongFound(filename) => vec![("filename", Left(filename))],
+            Self::NewSongFound(filename) | Self::ListenDropped(filename) =>
+            {
+                vec![("filename", Left(filename))]
+            }
+            Self::ParserTimedOut(plugin) | Self::ParserUnsupported(plugin) =>
+            {
+                vec![("plugin", Left(plugin))]
+            }
+            Self::OutputDeviceNotFound(device) => vec![("device", Left(device))],
+            Self::Equalizer(preset) | Self::EqualizerDefault(preset) =>
+            {
+                vec![("preset", Left(preset))]
+            }
             Self::Title(text)
             | Self::Album(text)
             | Self::Artist(text)
@@ -237,35 +379,106 @@ impl Message<'_>
             | Self::Track(text)
             | Self::TracksTotal(text)
             | Self::Duration(text)
-            | Self::SyncLyrics(text) => vec![("text", Left(text))],
-            Self::Lyrics(text) => vec![("text", Left(text.to_string()))],
-            Self::Comment(text) => vec![("text", Left(text.to_string()))],
+            | Self::Lyrics(text)
+            | Self::SyncLyrics(text)
+            | Self::Comment(text)
+            | Self::NotANumber(text)
+            | Self::LyricsLine(text) => vec![("text", Left(text))],
             Self::NumPictures(text) => vec![("text", Left(text.to_string()))],
-            Self::MetadataUnsupported(err) => vec![("err", Left(err))],
+            Self::ExtLink(description, link) =>
+            {
+                vec![("description", Left(description)), ("link", Left(link))]
+            }
+            Self::ExtText(description, text) =>
+            {
+                vec![("description", Left(description)), ("text", Left(text))]
+            }
+            Self::MetadataUnsupported(err)
+            | Self::NoOutputDevice(err)
+            | Self::WavOutputError(err)
+            | Self::NoNewSink(err)
+            | Self::SessionNotSaved(err)
+            | Self::TagsNotSaved(err)
+            | Self::LibraryNotSaved(err)
+            | Self::ListensNotSaved(err)
+            | Self::SubmittingListensFailed(err) => vec![("err", Left(err))],
             Self::LikelihoodIncreased(num) | Self::LikelihoodDecreased(num) =>
             {
                 vec![("likelihood", Right(FluentNumber::from(num)))]
             }
-            Self::MakingLouder(loud) | Self::MakingQuieter(loud) =>
+            Self::MakingLouder(loud) | Self::MakingQuieter(loud) | Self::ProposedVolume(loud) =>
             {
                 vec![("loud", Right(FluentNumber::from(loud * 100.0)))]
             }
+            Self::PlayingAtRate(rate) | Self::SongRate(rate) =>
+            {
+                vec![("rate", Right(FluentNumber::from(rate * 100.0)))]
+            }
+            Self::RealTimeLeft(rate, left) => vec![
+                ("rate", Right(FluentNumber::from(rate * 100.0))),
+                ("left", Right(FluentNumber::from(left))),
+            ],
+            Self::QuittingIn(left) | Self::PausingIn(left) =>
+            {
+                vec![("left", Right(FluentNumber::from(left.round())))]
+            }
+            Self::AlarmWaiting(time, left) => vec![
+                ("time", Left(time)),
+                ("left", Right(FluentNumber::from(left))),
+            ],
+            Self::InvalidAlarm(time) => vec![("time", Left(time))],
+            Self::SilenceDetected(start, end) | Self::Looping(start, end) => vec![
+                ("start", Right(FluentNumber::from(start))),
+                ("end", Right(FluentNumber::from(end))),
+            ],
             Self::DurationKnown(pos, len) => vec![
                 ("pos", Right(FluentNumber::from(pos))),
                 ("len", Right(FluentNumber::from(len))),
             ],
-            Self::DurationUnknown(pos) => vec![("pos", Right(FluentNumber::from(pos)))],
+            Self::DurationUnknown(pos)
+            | Self::SeekingTo(pos)
+            | Self::StartingAt(pos)
+            | Self::EndingAt(pos)
+            | Self::LoopStartMarked(pos)
+            | Self::ResumingAt(pos) =>
+            {
+                vec![("pos", Right(FluentNumber::from(pos)))]
+            }
             Self::ReadingSongProblem(path, err) => vec![
                 ("path", Left(format!("{:?}", path))),
                 ("err", Left(format!("{:?}", err))),
             ],
             Self::PlayingSong(s) => vec![("song", Left(s))],
+            Self::LoudnessMeasured(s, loudness) => vec![
+                ("song", Left(s)),
+                ("loudness", Right(FluentNumber::from(loudness))),
+            ],
             Self::SongLikelihood(num) => vec![("likelihood", Right(FluentNumber::from(num)))],
             Self::PositiveBonus(bonus) | Self::NegativeBonus(bonus) =>
             {
                 vec![("bonus", Right(FluentNumber::from(bonus)))]
             }
             Self::HelpNotice
+            | Self::PlayingCompletely
+            | Self::SleepTimerCancelled
+            | Self::NoSleepTimer
+            | Self::AlarmGoingOff
+            | Self::NoLoopStart
+            | Self::LoopTooLong
+            | Self::LoopCleared
+            | Self::RememberingPosition
+            | Self::NotRememberingPosition
+            | Self::EditTitle
+            | Self::EditArtist
+            | Self::EditAlbum
+            | Self::EditTrack
+            | Self::EditYear
+            | Self::TagsUnchanged
+            | Self::SongChangedWhileEditing
+            | Self::TagsSaved
+            | Self::ShowingLyrics
+            | Self::NotShowingLyrics
+            | Self::NoSyncLyrics
             | Self::SignalHandlerUnreachable
             | Self::PrintPlayingSong
             | Self::ExtLinks
@@ -314,6 +527,8 @@ impl Message<'_>
         }
     }
 
+    // See above.
+    #[allow(clippy::too_many_lines)]
     pub const fn loglevel(&self) -> LogLevel
     {
         match self
@@ -323,7 +538,8 @@ impl Message<'_>
             | Self::PrintInfoUnreachable
             | Self::CommandReadingProblem
             | Self::SavingStateErr
-            | Self::TooManyTries => LogLevel::Error,
+            | Self::TooManyTries
+            | Self::WavOutputError(_) => LogLevel::Error,
             Self::UnknownCommandChar(_)
             | Self::UnknownCommandByte(_)
             | Self::MprisHandlerError(_)
@@ -338,7 +554,25 @@ impl Message<'_>
             | Self::AlreadyRepeatingOnce
             | Self::AlreadyRepeatingForever
             | Self::AlreadyPlayingFirst
-            | Self::ReadingSongProblem(_, _) => LogLevel::Warn,
+            | Self::ReadingSongProblem(_, _)
+            | Self::OutputDeviceNotFound(_)
+            | Self::NoOutputDevice(_)
+            | Self::NoNewSink(_)
+            | Self::NoSleepTimer
+            | Self::InvalidAlarm(_)
+            | Self::NoLoopStart
+            | Self::LoopTooLong
+            | Self::SessionNotSaved(_)
+            | Self::LibraryNotSaved(_)
+            | Self::ParserTimedOut(_)
+            | Self::ParserUnsupported(_)
+            | Self::ListensNotSaved(_)
+            | Self::SubmittingListensFailed(_)
+            | Self::ListenDropped(_)
+            | Self::NotANumber(_)
+            | Self::SongChangedWhileEditing
+            | Self::TagsNotSaved(_)
+            | Self::NoSyncLyrics => LogLevel::Warn,
             Self::TotalPlayingLikelihood(_)
             | Self::HelpNotice
             | Self::NewSongFound(_)
@@ -371,8 +605,37 @@ impl Message<'_>
             | Self::StateSaved
             | Self::Previous
             | Self::PositiveBonus(_)
-            | Self::NegativeBonus(_) => LogLevel::Info,
-            Self::InSignalHandler(_) | Self::PrintPlayingSong => LogLevel::Debug,
+            | Self::NegativeBonus(_)
+            | Self::SeekingTo(_)
+            | Self::ProposedVolume(_)
+            | Self::PlayingAtRate(_)
+            | Self::SongRate(_)
+            | Self::RealTimeLeft(_, _)
+            | Self::Equalizer(_)
+            | Self::EqualizerDefault(_)
+            | Self::StartingAt(_)
+            | Self::EndingAt(_)
+            | Self::PlayingCompletely
+            | Self::SilenceDetected(_, _)
+            | Self::QuittingIn(_)
+            | Self::PausingIn(_)
+            | Self::SleepTimerCancelled
+            | Self::AlarmWaiting(_, _)
+            | Self::AlarmGoingOff
+            | Self::LoopStartMarked(_)
+            | Self::Looping(_, _)
+            | Self::LoopCleared
+            | Self::RememberingPosition
+            | Self::NotRememberingPosition
+            | Self::ResumingAt(_)
+            | Self::TagsUnchanged
+            | Self::TagsSaved
+            | Self::ShowingLyrics
+            | Self::NotShowingLyrics => LogLevel::Info,
+            Self::InSignalHandler(_) | Self::PrintPlayingSong | Self::LoudnessMeasured(_, _) =>
+            {
+                LogLevel::Debug
+            }
             Self::Title(_)
             | Self::Album(_)
             | Self::Artist(_)
@@ -391,11 +654,19 @@ impl Message<'_>
             | Self::Comment(_)
             | Self::NumPictures(_)
             | Self::ExtLinks
+            | Self::ExtLink(_, _)
             | Self::ExtTexts
+            | Self::ExtText(_, _)
             | Self::HelpHeader
             | Self::UnknownTitle
             | Self::UnknownArtist
             | Self::ControllerOut
+            | Self::EditTitle
+            | Self::EditArtist
+            | Self::EditAlbum
+            | Self::EditTrack
+            | Self::EditYear
+            | Self::LyricsLine(_)
             | Self::Description(_) => LogLevel::Println,
             Self::SignalHandlerUnreachable => LogLevel::Unreachable,
         }
@@ -29,6 +29,9 @@
 //! standard values are 10 and 10% respectively).  Then it will choose a
 //! song at random with the probability proportional to it's playing
 //! likelihood and plays it, unless you request something different.
+//! The title, artist, album, track number and duration of every song are
+//! kept in an index (`~/.zvavybir/legacylisten/library.csv`), so that
+//! their tags are only read again when the files change.
 //!
 //! The volume is adjustable on a per-song basis and is saved.  Although
 //! simple (ridiculously trivial indeed) to implement, there is no way to
@@ -43,14 +46,15 @@
 //! strangest one is that `legacylisten` will sever all connection to the
 //! disk if the *NIX signal `SIGUSR1` arrives and only starts reading
 //! again when `SIGUSR2` arrives.  `SIGUSR1` doesn't interrupt an already
-//! playing song since songs are buffered[^3].
+//! playing song as long as enough of it was read in advance[^3].
 //!
 //! ## Commands
 //! Commands are how `legacylisten` is controlled and consist always out
 //! of a single character.  Originally these were the first letter of the
 //! command name, but since this caused rather strange names (like `f` –
 //! "*f*ainter" – to decrease the volume), I settled to just number them
-//! through alphabetically.
+//! through alphabetically.  After `z` the numbering continues with the
+//! capital letters.
 //!
 //! To execute a command, just type it's letter (but remember that
 //! terminals are usually line-buffered, meaning that until you press
@@ -67,22 +71,26 @@
 //! * `d`: Pauses playing.
 //! * `e`: Resumes playing after pausing with `d` or `l` (doesn't
 //!   overwrite `SIGUSR1` though).
-//! * `f`: Skips song.
+//! * `f`: Skips song (with a short fade out).
 //! * `g`: Increases permanently the volume of the current song by 1% (but
 //!   not above 100%).
 //! * `h`: Decreases permanently the volume of the current song by 1% (but
 //!   not below 0%).
 //! * `i`: Shows how long the song is already playing and – if
-//!   available[^5] – how long it will take in total.
+//!   available[^5] – how long it will take in total (and how long the
+//!   sleep timer still runs and which part is looped).
 //! * `j`: Switches between playing and pausing.
 //! * `k`: Quits `legacylisten` as soon as the current song has finished
 //!   playing (takes precedence over `l`).
 //! * `l`: Like `k`, but just pauses instead of quitting.
-//! * `m`: Shows the metadata in the song's id3 tag (the length usually
-//!   has to be queried by `i` since it's rarely saved in the id3 tag).
+//! * `m`: Shows the metadata in the song's tag (ID3, Vorbis comments,
+//!   MP4, APE and RIFF INFO are understood).
 //! * `n`: Opens the cover image of the song in your preferred image viewer
 //!   (it uses `mimeopen` which is AFAIK not available on MS Windows, so
-//!   this won't work there).  If the song has no cover it opens
+//!   this won't work there).  The cover is the picture in the song's tag
+//!   or else an image called `cover`, `folder`, `front`, `album` or
+//!   `albumart` (e.g. `folder.jpg`) in the song's directory.  If the song
+//!   has no cover it opens
 //!   `~/.zvavybir/legacylisten/default.png` (doesn't need to be an PNG
 //!   file) instead.  For the fallback image I use (and made, so it's
 //!   quite bad) see
@@ -94,9 +102,54 @@
 //! * `r`: Skips to the beginning of the current song or – if it already
 //!   is at the beginning – to the previous one.  You can go as many songs
 //!   back as you want (or more precisely how many there are).  All played
-//!   songs are saved (but only in one run of `legacylisten`, if you
-//!   restart it the history is lost) and if you went back the next song
-//!   is the same as previously followed on that song.
+//!   songs are saved (also across restarts in
+//!   `~/.zvavybir/legacylisten/session.csv`) and if you went back the
+//!   next song is the same as previously followed on that song.
+//! * `s`: Seeks 10 seconds forward.
+//! * `t`: Seeks 10 seconds backward.
+//! * `u`: Seeks a minute forward.
+//! * `v`: Seeks a minute backward.
+//! * `w`: Seeks to the second typed directly before it (e.g. `90w` jumps
+//!   to 1:30) or – without a number – to the beginning of the song.
+//!   Seeking backward has to decode the song again from the beginning, so
+//!   it isn't instantaneous for long songs.
+//! * `x`: Plays faster by 10% (but not faster than 400%).  Like on a
+//!   record player the pitch changes with the speed.
+//! * `y`: Plays slower by 10% (but not slower than 25%).
+//! * `z`: Plays at the speed (in percent) typed directly before it
+//!   (e.g. `150z`) or – without a number – at normal speed.
+//! * `A`: Increases permanently the speed of the current song by 10%
+//!   (on top of the speed set with `x`, `y` and `z`).  Useful for
+//!   example for audio books or podcasts.
+//! * `B`: Decreases permanently the speed of the current song by 10%.
+//! * `C`: Switches the current song permanently to the next equalizer
+//!   preset (see [below](#equalizer)).  After the last preset it uses the
+//!   default one again.
+//! * `D`: Lets the current song permanently start at the current position
+//!   (e.g. to skip a long intro).
+//! * `E`: Lets the current song permanently end at the current position
+//!   (e.g. to skip silence or a hidden track at the end) and ends it
+//!   right away.
+//! * `F`: Plays the current song permanently from the beginning to the
+//!   end again (undoes `D` and `E`).
+//! * `G`: Quits `legacylisten` after the minutes typed directly before it
+//!   (e.g. `30G` quits in half an hour), fading out over the last
+//!   minute.  Without a number it cancels that again.
+//! * `H`: Like `G`, but just pauses instead of quitting.
+//! * `I`: Marks the start of a loop at the current position.
+//! * `J`: Marks the end of the loop at the current position and plays the
+//!   part between the two marks again and again (at most five minutes).
+//!   `o` ends the loop after its current pass, seeking out of it,
+//!   skipping or repeating the whole song end it right away.
+//! * `K`: Toggles whether the position in the current song is remembered
+//!   (see `resume_min_secs` and `resume_dir` below); this overrides the
+//!   conffile for this song permanently.
+//! * `L`: Asks one after another for a new title, artist, album, track
+//!   number and year of the current song and writes them into its tag
+//!   (just pressing enter keeps the old value).
+//! * `M`: Toggles showing the synchronized lyrics; every line is printed
+//!   when it's sung.  They are taken from a `.lrc` file with the same
+//!   name next to the song or else from its ID3 tag.
 //!
 //! ## Low memory handler
 //! As already briefly pointed out previously, especially older versions
@@ -134,7 +187,7 @@
 //! ignore_ram,false,
 //! lang,german,
 //! ```
-//! There are currently four possible options:
+//! These are the possible options:
 //! * `data_dir`: If you have your music collection somewhere else (like
 //!   me on an external hard drive or in `~/Music`) you can use this
 //!   option to change the directory `legacylisten` will search.  The `~/`
@@ -164,17 +217,163 @@
 //!     ```
 //!     The path has no requirements about filename or file
 //!     extension, but the language identifier *has* to be correct.
+//! * `read_ahead`: How many bytes of the playing song are read in advance
+//!   (default 16 MiB).  Larger values use more memory, but let a song
+//!   play longer after `SIGUSR1`.
+//! * `crossfade_ms`: If not zero (the default), the end of a song and the
+//!   beginning of the next one are faded into each other for that many
+//!   milliseconds.  Each song keeps its own volume during that.  Songs
+//!   whose length is unknown can't be crossfaded, but are still played
+//!   without a gap.
+//! * `crossfade_repeat`: Whether a song that is repeated is crossfaded
+//!   into itself too (default `false`).
+//! * `skip_fade_ms`: How many milliseconds a song fades out when it's
+//!   skipped (default 300).
+//! * `normalization`: Whether the loudness of the songs is measured in
+//!   the background (according to EBU R 128, or taken from the
+//!   Replay Gain tags if there are some) to save you from adjusting the
+//!   volume of quiet songs by hand.  Every song is measured only once
+//!   and the result is stored in `songs.csv`.  There are three possible
+//!   values:
+//!   * `off`: Nothing is measured (this is the default).
+//!   * `propose`: When a song is played, the volume it would have if it
+//!     were normalized is shown.
+//!   * `apply`: The volume is automatically adjusted to the measured
+//!     loudness.  The per-song volume set with `g` and `h` is still
+//!     applied on top of that, so 10% means "as loud as
+//!     `loudness_target`".
+//! * `loudness_target`: The loudness in LUFS songs are normalized to
+//!   (default -18, like Replay Gain).
+//! * `rate`: The speed songs are played at when `legacylisten` starts
+//!   (default 1, i.e. normal speed; 1.5 means 150%).
+//! * `equalizer`: The equalizer preset songs are played with if no other
+//!   one was chosen for them with `C` (default `flat`, i.e. unchanged).
+//! * `detect_silence`: If `true` (default `false`), the silence at the
+//!   beginning and the end of a song is detected in the background when
+//!   it's played the first time and skipped from then on, as if set with
+//!   `D` and `E`.
+//! * `output`: Where the sound goes to.  If there's no usable sound card,
+//!   it's discarded (like with `null`) instead of failing.
+//!   * `device`: To the sound card (this is the default).
+//!   * `null`: Nowhere; useful for testing or running in a container.
+//!   * `wav`: Into the WAV file `output_file`.  Pauses (and the time
+//!     until the alarm goes off) aren't written, only what was played.
+//! * `output_device`: The name of the sound card to use if there's more
+//!   than one (default: the system's default one).
+//! * `output_file`: The WAV file the sound is written to with `output`
+//!   set to `wav` (default `~/.zvavybir/legacylisten/output.wav`).  It's
+//!   overwritten every time `legacylisten` starts.  The samples are
+//!   stored as 32 bit floats.
+//! * `output_speed`: How many times faster than real time the sound is
+//!   played with `output` set to `null` or `wav` (default 1).  If it's 0,
+//!   it's done as fast as possible.
+//! * `alarm`: The time (like `6:45`) at which `legacylisten` starts
+//!   playing; see [Alarm](#alarm).  By default it starts right away.
+//! * `alarm_ramp_ms`: Over how many milliseconds the volume is raised
+//!   after the alarm went off (default 60000).
+//! * `alarm_songs`: A directory in `data_dir` with the songs that are
+//!   played while the volume is raised after the alarm went off
+//!   (default: all songs).
+//! * `resume_min_secs`: The position in songs that are at least this many
+//!   seconds long is remembered (default 1800).  When such a song is
+//!   quit or skipped, it goes on there the next time it's chosen; only
+//!   when it was played to its end it starts from the beginning again.
+//!   With 0 this isn't done for any song because of its length.
+//! * `resume_dir`: A directory in `data_dir` (e.g. with audiobooks or
+//!   podcasts) in whose songs the position is always remembered.  Can be
+//!   given more than once.
+//! * `continue_song`: If `true` (default `false`), the song that was
+//!   playing when `legacylisten` was quit the last time is played first
+//!   again from where it was interrupted; otherwise a new song is chosen.
+//!   The history for `r` is kept either way.
+//! * `art_cache_size`: The pictures in the songs' tags are stored in
+//!   `~/.zvavybir/legacylisten/art` for `n` and MPRIS; when it gets
+//!   bigger than this many MiB (default 50), the pictures that weren't
+//!   used the longest are deleted.
+//! * `parser_timeout_ms`: How long a parser plugin may take (default
+//!   2000) unless it says otherwise.
+//! * `listenbrainz_token`: If set, every song that was played for at
+//!   least half of it (or four minutes) is submitted as a listen to
+//!   [ListenBrainz](https://listenbrainz.org) with this user token.
+//!   Listens are kept in `~/.zvavybir/legacylisten/listens.csv` until
+//!   they could be submitted, so nothing is lost without network or
+//!   while quit; failed submissions are tried again later and less
+//!   often the longer they fail.
+//! * `listenbrainz_url`: Where the listens are submitted to (default
+//!   `https://api.listenbrainz.org`), for other servers with the same
+//!   API.
+//!
+//! ## Equalizer
+//! Like the volume, the tone of every song can be adjusted with
+//! equalizer presets.  They are defined in
+//! `~/.zvavybir/legacylisten/equalizer.csv` (in the same format as the
+//! configuration file), where every line is one band of a preset.  A
+//! line consists of the name of the preset, the kind of filter (`peak`,
+//! `lowshelf` or `highshelf`), its frequency in Hz, the gain in dB and
+//! optionally its Q (default 0.71).  For example:
+//! ```text
+//! bass,lowshelf,120,6,
+//! vocal,peak,2500,4,1.2,
+//! vocal,lowshelf,100,-3,
+//! ```
+//! The preset `flat` (no change at all) always exists.  Which preset a
+//! song uses is stored in `songs.csv`.
+//!
+//! ## Alarm
+//! `legacylisten` can also wake you up.  If it's started with
+//! `--alarm 6:45` (or `alarm` is set in the configuration file), it
+//! waits without reading anything until it's 6:45 and then starts
+//! playing, with the volume raised slowly.  Until then the only command
+//! that works is quitting.  If `alarm_songs` is set, only the songs in
+//! that directory are played then (as long as there are any), until the
+//! volume is raised completely; afterwards all songs are played again.
 //!
 //! ## Plugin interface
 //! In case there is no metadata tag in the song, you can use the plugin
 //! interface to tell `legacylisten` the song's title and artist.  Every
 //! plugin is a shell script (or executable if you prefer) in the
 //! `~/.zvavybir/legacylisten/parser` directory (or sub directories
-//! thereof) and gets the song's file name (without new line character) as
-//! input (on stdin).  If the file name could be parsed it has to output
-//! the song's title and artist (delimited by zero bytes and optionally a
-//! trailing zero byte) on stdout.  If no zero bytes are in the output the
-//! parsing is treated as having failed and will be ignored.
+//! thereof).  They are asked one after another in the order of their
+//! file names until one of them could parse the file name; a plugin that
+//! takes longer than `parser_timeout_ms` is stopped and skipped.  What
+//! they found out is stored in the index of the songs, so they are only
+//! asked again about a song when its file changes.
+//!
+//! Plugins of the first version of the protocol get the song's file name
+//! (without new line character) as input (on stdin).  If the file name
+//! could be parsed they have to output the song's title and artist
+//! (delimited by zero bytes and optionally a trailing zero byte) on
+//! stdout.  If no zero bytes are in the output the parsing is treated as
+//! having failed and will be ignored.
+//!
+//! A plugin that speaks the second version says so in a line (usually a
+//! comment) near its beginning:
+//! ```text
+//! # legacylisten-parser: version=2 mode=server timeout_ms=500
+//! ```
+//! `mode` and `timeout_ms` are optional.  It gets the file name as a
+//! line on stdin and outputs a `key=value` line for everything it found
+//! out; the known keys are `title`, `artist`, `album`, `track`, `year`
+//! and `genre`, others are ignored.  If it outputs none of them, the
+//! parsing has failed.  With `mode=server` the plugin isn't started
+//! again for every song, but keeps running and reads one file name after
+//! another; its answer to each one has to end with an empty line (which
+//! alone means that it failed).  It should exit when its stdin is
+//! closed.
+//!
+//! ## Hooks
+//! Every executable in the `~/.zvavybir/legacylisten/hooks` directory (or
+//! sub directories thereof) is run when something happens, e.g. to
+//! control lights or a status bar.  `legacylisten` doesn't wait for them.
+//! What happened is in the environment variable `LEGACYLISTEN_EVENT`:
+//! `song-started`, `song-finished` (played to its end), `skipped`,
+//! `paused`, `resumed`, `likelihood-changed` or `volume-changed`.  The
+//! other variables tell what's known about the current song:
+//! `LEGACYLISTEN_SONG` (its path), `LEGACYLISTEN_TITLE`,
+//! `LEGACYLISTEN_ARTIST`, `LEGACYLISTEN_ALBUM`, `LEGACYLISTEN_DURATION`
+//! and `LEGACYLISTEN_POSITION` (in seconds; not at the start and end),
+//! `LEGACYLISTEN_LIKELIHOOD`, `LEGACYLISTEN_VOLUME` (in percent) and
+//! `LEGACYLISTEN_PAUSED`.  Those that aren't known are left out.
 //!
 //! ## Installing
 //! The simplest way to install `legacylisten` is with
@@ -185,6 +384,15 @@
 //! cargo install legacylisten
 //! ```
 //!
+//! Out of the box `legacylisten` plays MP3, FLAC, Vorbis and WAV.  FLAC
+//! and WAV keep their full resolution (like 24 bit); the volume, the
+//! equalizer and everything else are computed with floating point
+//! numbers, so nothing is lost before the sound card.  To also play
+//! Opus, AAC/M4A and ALAC, enable the `symphonia` feature:
+//! ```text
+//! cargo install legacylisten --features symphonia
+//! ```
+//!
 //! ## Contributing
 //! As every software `legacylisten` too always can be improved.  While
 //! I'm trying to get it usable alone, I don't have unlimited time and
@@ -210,10 +418,13 @@
 //!
 //! [^2]: Or "likelihood" for short.
 //!
-//! [^3]: This is of course quite bad on the memory footprint, but it's
-//!     the best I could manage so far (at least it's a whole magnitude
-//!     better than the worst implementation I had).  If you have an
-//!     better idea, **please** [contribute](#contributing)!
+//! [^3]: Songs aren't loaded completely into memory anymore (this was
+//!     quite bad on the memory footprint, especially with hour-long
+//!     mixes), but read while they are played with a configurable amount
+//!     (see `read_ahead` in the [configuration
+//!     file](#configuration-file)) read ahead.  If the rest of the song
+//!     is longer than that, playing stops when the read-ahead runs out
+//!     and continues after `SIGUSR2`.
 //!
 //! [^4]: This command is a bit special since it's handled differently
 //!     internally.  You can see this on the one hand directly by it's
@@ -223,14 +434,11 @@
 //!     the same line.
 //!
 //! [^5]: `legacylisten` tries to read it out of the metadata of the audio
-//!     file or – if that fails (which happen often, since the underlying
-//!     routine seems to be still work-in-progress) – decodes the whole
-//!     song a second time to get the length on a simple, but costly way
-//!     after a short waiting period.  Until that is fixed (if you can
-//!     help, **please** [contribute](#contributing)) I wouldn't recommend
-//!     skipping multiple songs in short sequence, since per song there's
-//!     one thread trying to decode it – even after it's already certain
-//!     that it's never needed.
+//!     file or – if that fails – decodes the whole song a second time to
+//!     get the length on a simple, but costly way.  Either way the length
+//!     is stored in `songs.csv`, so this is only necessary the first time
+//!     a song is played, and the decoding stops as soon as the song is
+//!     skipped.
 
 #![warn(
     clippy::all,
@@ -252,7 +460,12 @@
 // `mem::drop(…)`. TODO: align my opinion and community's one with
 // each other.
 #![allow(clippy::let_underscore_drop)]
+// `Duration::from_mins` and `Duration::from_hours` would need a very
+// new Rust.
+#![allow(clippy::duration_suboptimal_units)]
 
+mod alarm;
+mod art;
 mod audio;
 mod buffer;
 mod commands;
@@ -260,13 +473,26 @@ mod conffile;
 mod config;
 mod csv;
 mod dbus;
+mod decoder;
+mod equalizer;
 mod err;
 mod files;
 mod helpers;
+mod hooks;
 mod l10n;
+mod library;
+mod loudness;
+mod lyrics;
 mod matcher;
+mod metadata;
+mod output;
 mod parser;
+mod resume;
+mod scrobble;
+mod session;
+mod sleep;
 mod songs;
+mod tagging;
 mod threads;
 
 pub mod runner;
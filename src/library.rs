@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{self, File},
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    config::ArcConfig,
+    csv::{non_empty, parse_duration, Csv},
+    err::Error,
+    metadata::Metadata,
+    parser::SmallMetadata,
+};
+
+// Lies next to `songs.csv`.
+const LIBRARY_FILE: &str = "library.csv";
+
+/// What's known about a song without reading it again.
+#[derive(Clone, Debug, Default)]
+pub struct LibraryEntry
+{
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+    pub duration: Option<Duration>,
+    // Whether the parser plugins were already asked about what the
+    // tags don't say.
+    parsed: bool,
+    // To notice when the file was changed; the modification time is
+    // in nanoseconds since the epoch.
+    mtime: u128,
+    size: u64,
+}
+
+/// The index of the metadata of all songs in `data_dir`, so that their
+/// tags don't have to be read again on every start.  The songs are
+/// stored by the same name as in `songs.csv`.
+#[derive(Debug, Default)]
+pub struct Library
+{
+    entries: HashMap<String, LibraryEntry>,
+    // Whether it has to be saved.
+    changed: bool,
+}
+
+// The modification time and size of a file.
+fn stat(path: &Path) -> Option<(u128, u64)>
+{
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+
+    Some((mtime, metadata.len()))
+}
+
+impl Library
+{
+    /// Reads the index from the config directory; it's empty if there
+    /// is none yet or it can't be read.
+    pub fn read(config_dir: &Path) -> Self
+    {
+        let Ok(csv) = Csv::new(config_dir.join(LIBRARY_FILE))
+        else
+        {
+            return Self::default();
+        };
+
+        let entries = csv
+            .entries
+            .into_iter()
+            .filter(|entry| entry.len() == 9)
+            .filter_map(|mut entry| {
+                Some((
+                    mem::take(&mut entry[0]),
+                    LibraryEntry {
+                        mtime: entry[1].parse().ok()?,
+                        size: entry[2].parse().ok()?,
+                        title: non_empty(&mut entry[3]),
+                        artist: non_empty(&mut entry[4]),
+                        album: non_empty(&mut entry[5]),
+                        track: entry[6].parse().ok(),
+                        duration: parse_duration(entry.get(7)),
+                        parsed: entry[8] == "true",
+                    },
+                ))
+            })
+            .collect();
+
+        Self {
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Reads the tags of a song again if it's new or was changed since
+    /// it was indexed.  Returns whether it was changed, i.e. whether
+    /// what was stored about it elsewhere is outdated.
+    pub fn update(&mut self, name: &str, path: &Path) -> bool
+    {
+        let Some((mtime, size)) = stat(path)
+        else
+        {
+            return false;
+        };
+
+        let old = self.entries.get(name);
+        if old.is_some_and(|entry| entry.mtime == mtime && entry.size == size)
+        {
+            return false;
+        }
+        let known = old.is_some();
+
+        // Files without tags (or which can't be read) are still
+        // indexed, so that they aren't tried again every time.
+        let metadata = Metadata::read(path).unwrap_or_default();
+        self.entries.insert(
+            name.to_string(),
+            LibraryEntry {
+                title: metadata.title,
+                artist: metadata.artist,
+                album: metadata.album,
+                track: metadata.track,
+                duration: metadata.duration,
+                parsed: false,
+                mtime,
+                size,
+            },
+        );
+        self.changed = true;
+
+        known
+    }
+
+    /// Forgets all songs for which `keep` is false (e.g. since they
+    /// were deleted).
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut keep: F)
+    {
+        let len = self.entries.len();
+        self.entries.retain(|name, _| keep(name));
+        self.changed |= self.entries.len() != len;
+    }
+
+    // Stores what the parser plugins said about a song, unless it was
+    // changed in the meantime.
+    fn store_parsed(&mut self, name: &str, parsed: &LibraryEntry)
+    {
+        if let Some(entry) = self
+            .entries
+            .get_mut(name)
+            .filter(|entry| entry.mtime == parsed.mtime && entry.size == parsed.size)
+        {
+            *entry = parsed.clone();
+            self.changed = true;
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&LibraryEntry>
+    {
+        self.entries.get(name)
+    }
+
+    /// Writes the index into the config directory if anything changed.
+    pub fn save(&mut self, config_dir: &Path) -> Result<(), Error>
+    {
+        if !self.changed
+        {
+            return Ok(());
+        }
+
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(name, entry)| {
+                vec![
+                    name.clone(),
+                    entry.mtime.to_string(),
+                    entry.size.to_string(),
+                    entry.title.clone().unwrap_or_default(),
+                    entry.artist.clone().unwrap_or_default(),
+                    entry.album.clone().unwrap_or_default(),
+                    entry.track.map(|x| x.to_string()).unwrap_or_default(),
+                    entry
+                        .duration
+                        .map(|x| x.as_secs_f64().to_string())
+                        .unwrap_or_default(),
+                    entry.parsed.to_string(),
+                ]
+            })
+            .collect();
+        // So that the file doesn't change needlessly.
+        entries.sort();
+
+        File::create(config_dir.join(LIBRARY_FILE))?
+            .write_all(Csv { entries }.to_string().as_bytes())?;
+        self.changed = false;
+
+        Ok(())
+    }
+}
+
+impl ArcConfig
+{
+    // The name of a song in the index.
+    fn library_name(&self, path: &Path) -> Option<String>
+    {
+        path.strip_prefix(&self.conffile.data_dir)
+            .ok()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    /// What the index knows about a song; its tags are only read
+    /// again if it was changed since.
+    pub fn indexed(&self, path: &Path) -> Option<LibraryEntry>
+    {
+        let name = self.library_name(path)?;
+        let mut library = self.library.lock().ok()?;
+        library.update(&name, path);
+
+        library.get(&name).cloned()
+    }
+
+    /// Like `indexed`, but what the tags don't say is asked from the
+    /// parser plugins.  This happens only once for every version of a
+    /// song, since their answer is stored in the index as well.
+    pub fn indexed_parsed(&self, path: &Path) -> Option<LibraryEntry>
+    {
+        let mut entry = self.indexed(path)?;
+        if entry.parsed || (entry.title.is_some() && entry.artist.is_some())
+        {
+            return Some(entry);
+        }
+
+        // The index isn't locked meanwhile, since the plugins can take
+        // long.
+        let mut metadata = Metadata {
+            path: PathBuf::from(path),
+            title: entry.title.take(),
+            artist: entry.artist.take(),
+            album: entry.album.take(),
+            track: entry.track,
+            ..Metadata::default()
+        };
+        if let Some(parsed) = path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .and_then(|s| SmallMetadata::parse(s, self))
+        {
+            parsed.complete(&mut metadata);
+        }
+        entry.title = metadata.title;
+        entry.artist = metadata.artist;
+        entry.album = metadata.album;
+        entry.track = metadata.track;
+        entry.parsed = true;
+
+        if let (Some(name), Ok(mut library)) = (self.library_name(path), self.library.lock())
+        {
+            library.store_parsed(&name, &entry);
+        }
+
+        Some(entry)
+    }
+}
+
+impl LibraryEntry
+{
+    /// Fills in what isn't known yet (e.g. since a song's tags weren't
+    /// read completely).
+    pub fn complete(&self, metadata: &mut Metadata)
+    {
+        metadata.title = metadata.title.take().or_else(|| self.title.clone());
+        metadata.artist = metadata.artist.take().or_else(|| self.artist.clone());
+        metadata.album = metadata.album.take().or_else(|| self.album.clone());
+        metadata.track = metadata.track.or(self.track);
+        metadata.duration = metadata.duration.or(self.duration);
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    path::Path,
+    sync::{atomic::Ordering, mpsc::Sender, Arc},
+    thread,
+    time::Duration,
+};
+
+use ebur128::{EbuR128, Mode};
+
+use crate::{config::ArcConfig, decoder::SongDecoder, err::Error, metadata::Metadata, songs::Song};
+
+// How many samples are handed to the loudness meter at once.
+const BLOCK_SIZE: usize = 16 * 1024;
+
+// The loudness ReplayGain 2.0 normalizes to.
+const REPLAYGAIN_REFERENCE: f32 = -18.0;
+
+/// What is done with the measured loudness of the songs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization
+{
+    /// Nothing is measured.
+    Off,
+    /// The loudness is measured and the normalized volume is shown
+    /// when a song is played, but not used.
+    Propose,
+    /// The volume of a song is automatically adjusted to its
+    /// measured loudness.  The volume stored in `songs.csv` is then
+    /// only an offset on top of that.
+    Apply,
+}
+
+// Reads the track gain of the ReplayGain tags and converts it back to
+// the loudness it was calculated from.
+fn replaygain<P: AsRef<Path>>(path: P) -> Option<f32>
+{
+    let gain = Metadata::read(path)
+        .ok()?
+        .replaygain_track_gain?
+        .trim()
+        .trim_end_matches("dB")
+        .trim()
+        .parse::<f32>()
+        .ok()?;
+
+    Some(REPLAYGAIN_REFERENCE - gain)
+}
+
+// Measures the integrated loudness (in LUFS) of a song according to
+// EBU R 128.
+fn measure<P: AsRef<Path>>(path: P, config: &Arc<ArcConfig>) -> Result<f32, Error>
+{
+    if let Some(loudness) = replaygain(&path)
+    {
+        return Ok(loudness);
+    }
+
+    let mut decoder = SongDecoder::new(path, config.clone())?;
+    let mut meter = EbuR128::new(decoder.channels().into(), decoder.sample_rate(), Mode::I)?;
+    // Has to be a whole number of frames.
+    let block_size = BLOCK_SIZE * usize::from(decoder.channels());
+    let mut block = Vec::with_capacity(block_size);
+
+    loop
+    {
+        block.clear();
+        block.extend(decoder.by_ref().take(block_size));
+        if block.is_empty()
+        {
+            break;
+        }
+        meter.add_frames_f32(&block)?;
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(meter.loudness_global()? as f32)
+}
+
+/// Measures the loudness of every song that wasn't measured yet and
+/// sends it together with the index of the song.  Songs that can't
+/// be measured are reported as NaN, so that they aren't tried again.
+pub fn scan(songs: Vec<(usize, String)>, tx: &Sender<(usize, f32)>, config: &Arc<ArcConfig>)
+{
+    for (index, name) in songs
+    {
+        // Like the decoder thread, it's not allowed to touch the disk
+        // when reading is paused.
+        while config.reading_paused.load(Ordering::SeqCst)
+        {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        let loudness = measure(config.conffile.data_dir.join(name), config).unwrap_or(f32::NAN);
+
+        if tx.send((index, loudness)).is_err()
+        {
+            return;
+        }
+    }
+}
+
+impl Song
+{
+    /// The factor the volume of this song would have to be multiplied
+    /// with to bring it to the target loudness.  Without a usable
+    /// measurement it's `1.0`.
+    #[must_use]
+    pub fn normalizing_gain(&self, config: &ArcConfig) -> f32
+    {
+        match self.loudness
+        {
+            Some(loudness) if loudness.is_finite() =>
+            {
+                // Silent or extremely quiet songs shouldn't be made
+                // unbearably loud.
+                10.0_f32
+                    .powf((config.conffile.loudness_target - loudness) / 20.0)
+                    .clamp(0.1, 10.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// The factor the volume of this song is actually multiplied
+    /// with, i.e. the normalizing gain if normalization is applied.
+    #[must_use]
+    pub fn gain(&self, config: &ArcConfig) -> f32
+    {
+        if config.conffile.normalization == Normalization::Apply
+        {
+            self.normalizing_gain(config)
+        }
+        else
+        {
+            1.0
+        }
+    }
+}
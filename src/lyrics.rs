@@ -0,0 +1,229 @@
+use std::{fmt::Write, fs, path::Path, sync::atomic::Ordering, time::Duration};
+
+use lofty::id3::v2::TimestampFormat;
+
+use crate::{config::Config, l10n::messages::Message};
+
+/// A line of synchronized lyrics and when it's sung.
+pub type LyricsLine = (Duration, String);
+
+// `mm:ss.xx`; some programs write `mm:ss:xx` instead.
+fn parse_timestamp(timestamp: &str) -> Option<Duration>
+{
+    let (minutes, seconds) = timestamp.split_once(':')?;
+    let minutes = minutes.trim().parse::<u64>().ok()?;
+    let seconds = seconds.trim().replacen(':', ".", 1).parse::<f64>().ok()?;
+
+    (seconds >= 0.0).then(|| Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+// Parses an LRC file.  Every line can have more than one timestamp;
+// the `offset` tag (in milliseconds) moves all of them.
+fn parse_lrc(text: &str) -> Vec<LyricsLine>
+{
+    let mut lines = vec![];
+    let mut offset = 0_i64;
+
+    for line in text.lines()
+    {
+        let mut rest = line.trim();
+        let mut timestamps = vec![];
+
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|rest| rest.split_once(']'))
+        {
+            if let Some(value) = tag.strip_prefix("offset:")
+            {
+                offset = value.trim().parse().unwrap_or(offset);
+            }
+            else if let Some(timestamp) = parse_timestamp(tag)
+            {
+                timestamps.push(timestamp);
+            }
+            rest = after;
+        }
+
+        let rest = rest.trim();
+        lines.extend(
+            timestamps
+                .into_iter()
+                .map(|timestamp| (timestamp, rest.to_string())),
+        );
+    }
+
+    // A positive offset shows the lines earlier.
+    let shift = Duration::from_millis(offset.unsigned_abs());
+    for (timestamp, _) in &mut lines
+    {
+        *timestamp = if offset >= 0
+        {
+            timestamp.saturating_sub(shift)
+        }
+        else
+        {
+            *timestamp + shift
+        };
+    }
+
+    lines.sort_by_key(|(timestamp, _)| *timestamp);
+    lines
+}
+
+/// Reads the `.lrc` file next to a song; `None` if there isn't one.
+pub fn read_lrc(song: &Path) -> Option<Vec<LyricsLine>>
+{
+    let bytes = fs::read(song.with_extension("lrc")).ok()?;
+
+    Some(parse_lrc(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Converts the content of a SYLT frame; `None` if its timestamps
+/// are in MPEG frames instead of milliseconds.
+pub fn from_sylt(format: TimestampFormat, content: Vec<(u32, String)>) -> Option<Vec<LyricsLine>>
+{
+    (format == TimestampFormat::MS).then(|| {
+        content
+            .into_iter()
+            .map(|(timestamp, text)| {
+                (
+                    Duration::from_millis(timestamp.into()),
+                    text.trim().to_string(),
+                )
+            })
+            .collect()
+    })
+}
+
+// The line that is sung at `pos`; `None` before the first one.
+fn line_at(lyrics: &[LyricsLine], pos: Duration) -> Option<usize>
+{
+    lyrics
+        .partition_point(|(timestamp, _)| *timestamp <= pos)
+        .checked_sub(1)
+}
+
+/// How the lyrics are shown by `m`.
+pub fn format_lyrics(lyrics: &[LyricsLine]) -> String
+{
+    let mut formatted = String::new();
+
+    for (timestamp, text) in lyrics
+    {
+        let millis = timestamp.as_millis();
+        let _ = write!(
+            formatted,
+            "\n[{}:{:02}.{:02}] {text}",
+            millis / 60_000,
+            millis / 1000 % 60,
+            millis % 1000 / 10
+        );
+    }
+
+    formatted
+}
+
+impl Config
+{
+    /// Prints the line of the synchronized lyrics that is sung right
+    /// now if it wasn't already.  Since it only depends on the
+    /// position, pausing and seeking need no special care.
+    pub fn show_lyrics(&mut self)
+    {
+        if !self.lyrics
+        {
+            return;
+        }
+        self.read_tags();
+        let Some(Ok(metadata)) = &self.metadata
+        else
+        {
+            return;
+        };
+        let Some(pos) = self
+            .source
+            .pos_to_duration(self.arc_config.current_pos.load(Ordering::SeqCst))
+        else
+        {
+            return;
+        };
+
+        let line = line_at(&metadata.sync_lyrics, pos);
+
+        if line != self.lyrics_line
+        {
+            if let Some(line) = line
+            {
+                self.l10n
+                    .write(Message::LyricsLine(metadata.sync_lyrics[line].1.clone()));
+            }
+            self.lyrics_line = line;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn ms(ms: u64) -> Duration
+    {
+        Duration::from_millis(ms)
+    }
+
+    // The text of the line sung at `pos`.
+    fn at(lyrics: &[LyricsLine], pos: u64) -> Option<&str>
+    {
+        line_at(lyrics, ms(pos)).map(|line| lyrics[line].1.as_str())
+    }
+
+    #[test]
+    fn timestamps()
+    {
+        assert_eq!(parse_timestamp("01:02.50"), Some(ms(62_500)));
+        assert_eq!(parse_timestamp("01:02"), Some(ms(62_000)));
+        assert_eq!(parse_timestamp("1:02:25"), Some(ms(62_250)));
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("00:-1"), None);
+    }
+
+    #[test]
+    fn lines_by_position()
+    {
+        let lyrics = parse_lrc(
+            "[ti:Title]\n[00:01.00]First\n[00:03]Second\n\n[00:05.50] Third \nno timestamp\n",
+        );
+
+        assert_eq!(lyrics.len(), 3);
+        assert_eq!(at(&lyrics, 0), None);
+        assert_eq!(at(&lyrics, 999), None);
+        assert_eq!(at(&lyrics, 1000), Some("First"));
+        assert_eq!(at(&lyrics, 2999), Some("First"));
+        assert_eq!(at(&lyrics, 3000), Some("Second"));
+        assert_eq!(at(&lyrics, 5499), Some("Second"));
+        assert_eq!(at(&lyrics, 5500), Some("Third"));
+        assert_eq!(at(&lyrics, 600_000), Some("Third"));
+    }
+
+    #[test]
+    fn several_timestamps_per_line()
+    {
+        let lyrics = parse_lrc("[00:10.00][00:02.00]Chorus\n[00:05.00]Verse\n");
+
+        assert_eq!(lyrics.len(), 3);
+        assert_eq!(at(&lyrics, 2000), Some("Chorus"));
+        assert_eq!(at(&lyrics, 5000), Some("Verse"));
+        assert_eq!(at(&lyrics, 9999), Some("Verse"));
+        assert_eq!(at(&lyrics, 10_000), Some("Chorus"));
+    }
+
+    #[test]
+    fn offset()
+    {
+        let lyrics = parse_lrc("[offset:500]\n[00:00.20]Early\n[00:02.00]Late\n");
+        assert_eq!(lyrics[0].0, ms(0));
+        assert_eq!(lyrics[1].0, ms(1500));
+
+        let lyrics = parse_lrc("[offset:-500]\n[00:02.00]Late\n");
+        assert_eq!(lyrics[0].0, ms(2500));
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, FileType, TaggedFile, TaggedFileExt},
+    id3::v2::{Frame, FrameId, Id3v2Tag, SynchronizedTextFrame},
+    mpeg::MpegFile,
+    picture::{Picture, PictureType},
+    probe::Probe,
+    tag::{Accessor, ItemKey, ItemValue, Tag},
+};
+
+use crate::{
+    config::Config,
+    err::Error,
+    lyrics::{from_sylt, read_lrc, LyricsLine},
+};
+
+/// The metadata of a song, whatever tag format it's stored in (ID3,
+/// Vorbis comments, MP4 atoms, APE, …).
+#[derive(Clone, Debug, Default)]
+pub struct Metadata
+{
+    /// The file the metadata was read from.
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub date_recorded: Option<String>,
+    pub date_released: Option<String>,
+    pub disc: Option<u32>,
+    pub total_discs: Option<u32>,
+    pub track: Option<u32>,
+    pub total_tracks: Option<u32>,
+    /// Taken from the audio stream, not from the tag.
+    pub duration: Option<Duration>,
+    pub lyrics: Vec<String>,
+    /// From the `.lrc` file next to the song or else from the ID3
+    /// tag.
+    pub sync_lyrics: Vec<LyricsLine>,
+    pub comments: Vec<String>,
+    /// The front cover comes first.
+    pub pictures: Vec<Picture>,
+    pub links: Vec<(String, String)>,
+    pub texts: Vec<(String, String)>,
+    pub replaygain_track_gain: Option<String>,
+}
+
+// SYLT frames aren't converted into the generic tags, so they have to
+// be read before.  Only the first usable one is taken.
+fn sync_lyrics(tag: &Id3v2Tag) -> Vec<LyricsLine>
+{
+    let id = FrameId::Valid(Cow::Borrowed("SYLT"));
+
+    tag.into_iter()
+        .filter(|frame| frame.id() == &id)
+        .find_map(|frame| match frame
+        {
+            Frame::Binary(binary) => SynchronizedTextFrame::parse(&binary.data, frame.flags())
+                .ok()
+                .and_then(|sylt| from_sylt(sylt.timestamp_format, sylt.content)),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+// The first of the tags which has the item.
+fn first<T, F>(tags: &[&Tag], f: F) -> Option<T>
+where
+    F: Fn(&Tag) -> Option<T>,
+{
+    tags.iter().find_map(|tag| f(tag))
+}
+
+// The items of the first tag that has any, so that they don't appear
+// twice if the file has more than one tag.
+fn all<T, F>(tags: &[&Tag], f: F) -> Vec<T>
+where
+    F: Fn(&Tag) -> Vec<T>,
+{
+    tags.iter()
+        .map(|tag| f(tag))
+        .find(|items| !items.is_empty())
+        .unwrap_or_default()
+}
+
+fn strings(tag: &Tag, key: &ItemKey) -> Vec<String>
+{
+    tag.get_strings(key).map(ToOwned::to_owned).collect()
+}
+
+// What has no meaning for `legacylisten`, but is shown anyway.
+fn unknown_items(tag: &Tag, links: bool) -> Vec<(String, String)>
+{
+    tag.items()
+        .filter_map(|item| match (item.key(), item.value())
+        {
+            (ItemKey::Unknown(key), ItemValue::Locator(value)) if links =>
+            {
+                Some((key.clone(), value.clone()))
+            }
+            (ItemKey::Unknown(key), ItemValue::Text(value)) if !links =>
+            {
+                Some((key.clone(), value.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+impl Metadata
+{
+    /// Reads the metadata of a song.  The primary tag of the format
+    /// is preferred, but what's missing there is taken from the
+    /// others.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error>
+    {
+        let path = path.as_ref();
+        let probe = Probe::open(path)?.guess_file_type()?;
+        let mut sync = vec![];
+
+        let file = if probe.file_type() == Some(FileType::Mpeg)
+        {
+            let mpeg = MpegFile::read_from(&mut probe.into_inner(), ParseOptions::new())?;
+            if let Some(tag) = mpeg.id3v2()
+            {
+                sync = sync_lyrics(tag);
+            }
+            TaggedFile::from(mpeg)
+        }
+        else
+        {
+            probe.read()?
+        };
+
+        let primary = file.primary_tag_type();
+        let mut tags: Vec<_> = file.tags().iter().collect();
+        tags.sort_by_key(|tag| tag.tag_type() != primary);
+
+        let mut pictures = all(&tags, |tag| tag.pictures().to_vec());
+        pictures.sort_by_key(|picture| picture.pic_type() != PictureType::CoverFront);
+
+        let duration = file.properties().duration();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            title: first(&tags, |tag| tag.title().map(Cow::into_owned)),
+            album: first(&tags, |tag| tag.album().map(Cow::into_owned)),
+            artist: first(&tags, |tag| tag.artist().map(Cow::into_owned)),
+            album_artist: first(&tags, |tag| {
+                tag.get_string(&ItemKey::AlbumArtist).map(ToOwned::to_owned)
+            }),
+            year: first(&tags, Accessor::year),
+            genre: first(&tags, |tag| tag.genre().map(Cow::into_owned)),
+            date_recorded: first(&tags, |tag| {
+                tag.get_string(&ItemKey::RecordingDate)
+                    .map(ToOwned::to_owned)
+            }),
+            date_released: first(&tags, |tag| {
+                tag.get_string(&ItemKey::ReleaseDate).map(ToOwned::to_owned)
+            }),
+            disc: first(&tags, Accessor::disk),
+            total_discs: first(&tags, Accessor::disk_total),
+            track: first(&tags, Accessor::track),
+            total_tracks: first(&tags, Accessor::track_total),
+            duration: (!duration.is_zero()).then_some(duration),
+            lyrics: all(&tags, |tag| strings(tag, &ItemKey::Lyrics)),
+            sync_lyrics: read_lrc(path).unwrap_or(sync),
+            comments: all(&tags, |tag| strings(tag, &ItemKey::Comment)),
+            pictures,
+            links: all(&tags, |tag| unknown_items(tag, true)),
+            texts: all(&tags, |tag| unknown_items(tag, false)),
+            replaygain_track_gain: first(&tags, |tag| {
+                tag.get_string(&ItemKey::ReplayGainTrackGain)
+                    .map(ToOwned::to_owned)
+            }),
+        })
+    }
+}
+
+impl Config
+{
+    /// Takes the metadata of the current song from the index of the
+    /// library, so that its file doesn't have to be read again.
+    pub fn index_metadata(&mut self, path: &Path)
+    {
+        self.metadata = Some(self.arc_config.indexed(path).map_or_else(
+            || Metadata::read(path),
+            |entry| {
+                let mut metadata = Metadata {
+                    path: path.to_path_buf(),
+                    ..Metadata::default()
+                };
+                entry.complete(&mut metadata);
+                Ok(metadata)
+            },
+        ));
+        self.tags_read = false;
+    }
+
+    /// Reads everything from the tags of the current song (e.g. the
+    /// lyrics and pictures) if that didn't happen yet.
+    pub fn read_tags(&mut self)
+    {
+        if self.tags_read
+        {
+            return;
+        }
+        let Some(path) = &self.song_path
+        else
+        {
+            return;
+        };
+
+        let mut metadata = Metadata::read(path);
+        // The index might know more from the parser plugins.
+        if let (Ok(metadata), Some(Ok(indexed))) = (&mut metadata, &self.metadata)
+        {
+            metadata.title = metadata.title.take().or_else(|| indexed.title.clone());
+            metadata.artist = metadata.artist.take().or_else(|| indexed.artist.clone());
+            metadata.album = metadata.album.take().or_else(|| indexed.album.clone());
+            metadata.track = metadata.track.or(indexed.track);
+        }
+        self.metadata = Some(metadata);
+        self.tags_read = true;
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rodio::{
+    cpal::{self, traits::HostTrait},
+    dynamic_mixer::{mixer, DynamicMixer, DynamicMixerController},
+    DeviceTrait, OutputStream, OutputStreamHandle, Sink,
+};
+
+use crate::{
+    config::ArcConfig,
+    err::Error,
+    l10n::{messages::Message, L10n},
+};
+
+// What the headless backends produce.  The WAV file gets the samples
+// as 32 bit floats, so nothing is lost.
+const CHANNELS: u16 = 2;
+const SAMPLE_RATE: u32 = 44_100;
+
+// How many frames the headless backends process at once (10 ms).
+const BLOCK_FRAMES: usize = SAMPLE_RATE as usize / 100;
+const BLOCK_DURATION: Duration = Duration::from_millis(10);
+
+// How often the header of the WAV file is updated, so that it's
+// usable even if `legacylisten` is killed.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where the sound goes to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputKind
+{
+    /// A sound card (the default one or `output_device`).
+    Device,
+    /// Nowhere.
+    Null,
+    /// Into the WAV file `output_file`.
+    Wav,
+}
+
+/// The output backend all `Sink`s play on.
+pub enum Output
+{
+    Device
+    {
+        // Has to be kept alive as long as something is played.
+        _stream: OutputStream,
+        handle: OutputStreamHandle,
+    },
+    Headless(Arc<DynamicMixerController<f32>>),
+}
+
+// Opens the output device with that name or the default one if
+// there's no name.
+fn open_device(name: Option<&str>, l10n: L10n)
+    -> Result<(OutputStream, OutputStreamHandle), Error>
+{
+    if let Some(name) = name
+    {
+        let device = cpal::default_host()
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| {
+                devices.find(|device| device.name().is_ok_and(|device_name| device_name == name))
+            });
+
+        if let Some(device) = device
+        {
+            return Ok(OutputStream::try_from_device(&device)?);
+        }
+        l10n.write(Message::OutputDeviceNotFound(name.to_string()));
+    }
+
+    Ok(OutputStream::try_default()?)
+}
+
+// Pulls the mixed sound out of the mixer and writes it into the WAV
+// file (if there is one).  If `speed` isn't zero, this is done that
+// many times faster than real time, otherwise as fast as possible.
+// Blocks in which no song played (e.g. since it's paused or the alarm
+// didn't go off yet) aren't written, so that the file doesn't fill up
+// with silence.
+fn run_headless(
+    mut mixer: DynamicMixer<f32>,
+    mut wav: Option<WavWriter<BufWriter<File>>>,
+    speed: f32,
+    config: &ArcConfig,
+)
+{
+    let started = Instant::now();
+    let mut last_flush = started;
+    let mut frames = 0;
+    let mut block = vec![0.0; BLOCK_FRAMES * usize::from(CHANNELS)];
+
+    loop
+    {
+        let played = config.samples_played.load(Ordering::SeqCst);
+        for sample in &mut block
+        {
+            *sample = mixer.next().unwrap_or(0.0);
+        }
+        frames += BLOCK_FRAMES;
+
+        if played == config.samples_played.load(Ordering::SeqCst)
+        {
+            // Otherwise it would be a busy loop.
+            if speed <= 0.0
+            {
+                thread::sleep(BLOCK_DURATION);
+            }
+        }
+        else if let Some(writer) = &mut wav
+        {
+            // Like a sound card would.
+            if let Err(err) = block
+                .iter()
+                .try_for_each(|&sample| writer.write_sample(sample.clamp(-1.0, 1.0)))
+            {
+                config.l10n.write(Message::WavOutputError(err.to_string()));
+                wav = None;
+            }
+        }
+
+        if let Some(writer) = &mut wav
+        {
+            if last_flush.elapsed() >= FLUSH_INTERVAL
+            {
+                let _ = writer.flush();
+                last_flush = Instant::now();
+            }
+        }
+
+        if speed > 0.0
+        {
+            let target =
+                Duration::from_secs_f64(frames as f64 / f64::from(SAMPLE_RATE) / f64::from(speed));
+            if let Some(wait) = target.checked_sub(started.elapsed())
+            {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+impl Output
+{
+    /// Opens the output backend chosen in the conffile.  If no sound
+    /// card can be used, the sound is discarded instead of failing.
+    /// A sound card is opened with its preferred sample format and
+    /// rate; the samples are only converted to it at the very end.
+    pub fn new(config: &Arc<ArcConfig>) -> Result<Self, Error>
+    {
+        let conffile = &config.conffile;
+
+        let wav = match conffile.output
+        {
+            OutputKind::Device =>
+            {
+                match open_device(conffile.output_device.as_deref(), config.l10n)
+                {
+                    Ok((stream, handle)) =>
+                    {
+                        return Ok(Self::Device {
+                            _stream: stream,
+                            handle,
+                        })
+                    }
+                    Err(err) => config.l10n.write(Message::NoOutputDevice(err.to_string())),
+                }
+                None
+            }
+            OutputKind::Null => None,
+            OutputKind::Wav => Some(WavWriter::create(
+                &conffile.output_file,
+                WavSpec {
+                    channels: CHANNELS,
+                    sample_rate: SAMPLE_RATE,
+                    bits_per_sample: 32,
+                    sample_format: SampleFormat::Float,
+                },
+            )?),
+        };
+
+        let (controller, mixer) = mixer(CHANNELS, SAMPLE_RATE);
+        let speed = conffile.output_speed;
+        let config = config.clone();
+        thread::spawn(move || run_headless(mixer, wav, speed, &config));
+
+        Ok(Self::Headless(controller))
+    }
+
+    /// Creates a new `Sink` playing on this output.
+    pub fn new_sink(&self) -> Result<Sink, Error>
+    {
+        match self
+        {
+            Self::Device { handle, .. } => Ok(Sink::try_new(handle)?),
+            Self::Headless(controller) =>
+            {
+                let (sink, queue) = Sink::new_idle();
+                controller.add(queue);
+                Ok(sink)
+            }
+        }
+    }
+}
@@ -1,73 +1,511 @@
 use std::{
-    io::Write,
-    process::{Command, Stdio},
-    sync::Arc,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use walkdir::WalkDir;
 
-use crate::{config::ArcConfig, l10n::messages::Message};
+use crate::{config::ArcConfig, l10n::messages::Message, metadata::Metadata};
+
+// A plugin which speaks a newer version of the protocol says so in a
+// line (usually a comment) near its beginning, e.g.
+// `# legacylisten-parser: version=2 mode=server timeout_ms=500`.
+const HEADER: &str = "legacylisten-parser:";
+// How much of a plugin is searched for the header.
+const HEADER_LEN: u64 = 4096;
+// The newest version of the protocol.
+const VERSION: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode
+{
+    // Started again for every song.
+    Oneshot,
+    // Started once and then asked for one song after another.
+    Server,
+}
 
 #[derive(Clone, Debug)]
+struct Plugin
+{
+    path: PathBuf,
+    version: u32,
+    mode: Mode,
+    timeout: Duration,
+}
+
+/// What a parser plugin found out about a song from its file name.
+#[derive(Clone, Debug, Default)]
 pub struct SmallMetadata
 {
-    pub name: String,
-    pub artist: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
 }
 
-impl SmallMetadata
+/// A plugin in server mode that is still running.
+#[derive(Debug)]
+pub struct Server
+{
+    child: Child,
+    stdin: ChildStdin,
+    // Filled by a thread, so that waiting for an answer can time out.
+    lines: Receiver<String>,
+}
+
+impl Drop for Server
 {
-    fn from(s: &str, config: &Arc<ArcConfig>) -> Option<Self>
+    fn drop(&mut self)
     {
-        for entry in WalkDir::new(config.config_dir.join("parser"))
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Plugin
+{
+    fn new(path: PathBuf, config: &ArcConfig) -> Self
+    {
+        let mut start = vec![];
+        let _ = File::open(&path).and_then(|file| file.take(HEADER_LEN).read_to_end(&mut start));
+
+        Self::with_header(
+            path,
+            &String::from_utf8_lossy(&start),
+            Duration::from_millis(config.conffile.parser_timeout_ms),
+        )
+    }
+
+    // `start` is the beginning of the plugin, where the header is
+    // searched.
+    fn with_header(path: PathBuf, start: &str, timeout: Duration) -> Self
+    {
+        let mut plugin = Self {
+            path,
+            version: 1,
+            mode: Mode::Oneshot,
+            timeout,
+        };
+
+        let Some(options) = start
+            .lines()
+            .find_map(|line| line.split_once(HEADER).map(|(_, options)| options))
+        else
+        {
+            return plugin;
+        };
+
+        for option in options.split_whitespace()
         {
-            let mut parser = if let Ok(parser) = Command::new(entry.ok()?.path().as_os_str())
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
+            match option.split_once('=')
             {
-                parser
+                Some(("version", version)) =>
+                {
+                    // Unknown versions are remembered, so that the
+                    // plugin isn't misunderstood.
+                    plugin.version = version.parse().unwrap_or(u32::MAX);
+                }
+                Some(("mode", "server")) => plugin.mode = Mode::Server,
+                Some(("timeout_ms", timeout)) =>
+                {
+                    let _ = timeout
+                        .parse()
+                        .map(|x| plugin.timeout = Duration::from_millis(x));
+                }
+                _ =>
+                {}
             }
-            else
+        }
+        // The first version knows nothing of servers.
+        if plugin.version < 2
+        {
+            plugin.mode = Mode::Oneshot;
+        }
+
+        plugin
+    }
+
+    fn name(&self) -> String
+    {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    // Starts the plugin, gives it the file name and returns what it
+    // wrote; `None` if it couldn't be started (e.g. since it isn't
+    // executable).
+    fn run(&self, s: &str) -> Option<Result<Vec<u8>, RecvTimeoutError>>
+    {
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut stdin = child.stdin.take()?;
+        let mut stdout = child.stdout.take()?;
+
+        // The first version gets it without a new line character.  If
+        // the plugin doesn't read it, it's not needed anyway.
+        let _ = if self.version < 2
+        {
+            write!(stdin, "{s}")
+        }
+        else
+        {
+            writeln!(stdin, "{s}")
+        };
+        drop(stdin);
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let mut output = vec![];
+            let _ = stdout.read_to_end(&mut output);
+            let _ = tx.send(output);
+        });
+
+        let output = rx.recv_timeout(self.timeout);
+        if output.is_err()
+        {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+
+        Some(output)
+    }
+
+    fn start_server(&self) -> Option<Server>
+    {
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let stdin = child.stdin.take()?;
+        let stdout = child.stdout.take()?;
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines()
             {
-                continue;
-            };
+                let Ok(line) = line
+                else
+                {
+                    break;
+                };
+                if tx.send(line).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Some(Server {
+            child,
+            stdin,
+            lines: rx,
+        })
+    }
+}
 
-            let mut stdin = parser.stdin.take()?;
+impl Server
+{
+    // Sends the file name and returns the lines of the answer (which
+    // ends with an empty line); `None` if it took too long or the
+    // server doesn't run anymore.
+    fn ask(&mut self, s: &str, timeout: Duration) -> Option<Vec<String>>
+    {
+        writeln!(self.stdin, "{s}").ok()?;
+        self.stdin.flush().ok()?;
 
-            write!(stdin, "{}", s).ok()?;
+        let deadline = Instant::now() + timeout;
+        let mut lines = vec![];
+        loop
+        {
+            let line = self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .ok()?;
+            if line.is_empty()
+            {
+                return Some(lines);
+            }
+            lines.push(line);
+        }
+    }
+}
 
-            let output = parser.wait_with_output().ok()?.stdout;
-            let output_split = output.split(|&c| c == b'\0').collect::<Vec<_>>();
+// `title` and `artist` delimited by zero bytes; failed if there are
+// less than two zero bytes, like it always was.
+fn parse_v1(output: &[u8]) -> Option<SmallMetadata>
+{
+    let output_split = output.split(|&c| c == b'\0').collect::<Vec<_>>();
 
-            if output_split.len() > 2
+    (output_split.len() > 2).then(|| SmallMetadata {
+        title: Some(String::from_utf8_lossy(output_split[0]).to_string()),
+        artist: Some(String::from_utf8_lossy(output_split[1]).to_string()),
+        ..SmallMetadata::default()
+    })
+}
+
+// One `key=value` line for everything that is known; unknown keys
+// are ignored, so that later versions can add new ones.  Failed if
+// nothing is known.
+fn parse_v2<I, S>(lines: I) -> Option<SmallMetadata>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut metadata = SmallMetadata::default();
+    let mut found = false;
+
+    for line in lines
+    {
+        let Some((key, value)) = line.as_ref().split_once('=')
+        else
+        {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty()
+        {
+            continue;
+        }
+
+        match key.trim()
+        {
+            "title" => metadata.title = Some(value.to_string()),
+            "artist" => metadata.artist = Some(value.to_string()),
+            "album" => metadata.album = Some(value.to_string()),
+            "track" => metadata.track = value.parse().ok(),
+            "year" => metadata.year = value.parse().ok(),
+            "genre" => metadata.genre = Some(value.to_string()),
+            _ => continue,
+        }
+        found = true;
+    }
+
+    found.then_some(metadata)
+}
+
+// All plugins in the order they are asked; the order of the file
+// names, so that it's always the same.
+fn plugins(config: &ArcConfig) -> Vec<Plugin>
+{
+    WalkDir::new(config.config_dir.join("parser"))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_type().is_dir())
+        .map(|entry| Plugin::new(entry.into_path(), config))
+        .collect()
+}
+
+// The running server of a plugin; it's started if there's none yet.
+fn server(plugin: &Plugin, config: &ArcConfig) -> Option<Arc<Mutex<Server>>>
+{
+    let mut servers = config.parser_servers.lock().ok()?;
+    if !servers.contains_key(&plugin.path)
+    {
+        let server = plugin.start_server()?;
+        servers.insert(plugin.path.clone(), Arc::new(Mutex::new(server)));
+    }
+
+    servers.get(&plugin.path).cloned()
+}
+
+// Asks a single plugin.  Only a server is locked while it's asked, so
+// that a slow plugin doesn't hold up other threads asking another one.
+fn ask(plugin: &Plugin, s: &str, config: &ArcConfig) -> Option<SmallMetadata>
+{
+    if plugin.version > VERSION
+    {
+        config.l10n.write(Message::ParserUnsupported(plugin.name()));
+        return None;
+    }
+
+    // The protocol is line based, so the name has to be a single
+    // line.
+    let line = s.replace('\n', " ");
+
+    match plugin.mode
+    {
+        Mode::Oneshot =>
+        {
+            let Ok(output) = plugin.run(if plugin.version < 2 { s } else { &line })?
+            else
+            {
+                config.l10n.write(Message::ParserTimedOut(plugin.name()));
+                return None;
+            };
+
+            if plugin.version < 2
+            {
+                parse_v1(&output)
+            }
+            else
+            {
+                parse_v2(String::from_utf8_lossy(&output).lines())
+            }
+        }
+        Mode::Server =>
+        {
+            let server = server(plugin, config)?;
+            let answer = server.lock().ok()?.ask(&line, plugin.timeout);
+            if answer.is_none()
             {
-                return Some(Self {
-                    name: String::from_utf8_lossy(output_split[0]).to_string(),
-                    artist: String::from_utf8_lossy(output_split[1]).to_string(),
-                });
+                // It's started again for the next song (unless another
+                // thread did that already).
+                if let Ok(mut servers) = config.parser_servers.lock()
+                {
+                    if servers
+                        .get(&plugin.path)
+                        .is_some_and(|running| Arc::ptr_eq(running, &server))
+                    {
+                        servers.remove(&plugin.path);
+                    }
+                }
+                config.l10n.write(Message::ParserTimedOut(plugin.name()));
             }
+
+            parse_v2(answer?)
         }
+    }
+}
+
+impl SmallMetadata
+{
+    /// Asks the parser plugins one after another about a file name
+    /// until one knows something.
+    pub fn parse(s: &str, config: &ArcConfig) -> Option<Self>
+    {
+        let plugins = plugins(config);
+
+        // Stops the servers whose plugin was removed.
+        config
+            .parser_servers
+            .lock()
+            .ok()?
+            .retain(|path, _| plugins.iter().any(|plugin| &plugin.path == path));
+
+        plugins.iter().find_map(|plugin| ask(plugin, s, config))
+    }
+
+    /// Fills in what isn't known from the tags.
+    pub fn complete(self, metadata: &mut Metadata)
+    {
+        metadata.title = metadata.title.take().or(self.title);
+        metadata.artist = metadata.artist.take().or(self.artist);
+        metadata.album = metadata.album.take().or(self.album);
+        metadata.track = metadata.track.or(self.track);
+        metadata.year = metadata.year.or(self.year);
+        metadata.genre = metadata.genre.take().or(self.genre);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(2000);
+
+    fn header(start: &str) -> Plugin
+    {
+        Plugin::with_header(PathBuf::from("plugin"), start, TIMEOUT)
+    }
+
+    #[test]
+    fn v1_needs_two_zero_bytes()
+    {
+        let parsed = parse_v1(b"Title\0Artist\0").unwrap();
+        assert_eq!(parsed.title.as_deref(), Some("Title"));
+        assert_eq!(parsed.artist.as_deref(), Some("Artist"));
+        assert!(parsed.album.is_none());
+
+        // Whatever comes after the second one is ignored.
+        let parsed = parse_v1(b"Title\0Artist\0rest").unwrap();
+        assert_eq!(parsed.artist.as_deref(), Some("Artist"));
+
+        assert!(parse_v1(b"Title\0Artist").is_none());
+        assert!(parse_v1(b"Title Artist").is_none());
+        assert!(parse_v1(b"").is_none());
+    }
+
+    #[test]
+    fn v2_key_value_lines()
+    {
+        let parsed = parse_v2([
+            "title=Some Title",
+            " artist = Some Artist ",
+            "album=",
+            "track=3",
+            "year=no number",
+            "mood=happy",
+            "no key value pair",
+            "genre=a=b",
+        ])
+        .unwrap();
+        assert_eq!(parsed.title.as_deref(), Some("Some Title"));
+        assert_eq!(parsed.artist.as_deref(), Some("Some Artist"));
+        assert!(parsed.album.is_none());
+        assert_eq!(parsed.track, Some(3));
+        assert!(parsed.year.is_none());
+        assert_eq!(parsed.genre.as_deref(), Some("a=b"));
+    }
+
+    #[test]
+    fn v2_fails_without_known_keys()
+    {
+        assert!(parse_v2(["mood=happy", "title=", "artist=  "]).is_none());
+        assert!(parse_v2(Vec::<String>::new()).is_none());
+    }
+
+    #[test]
+    fn header_options()
+    {
+        let plugin = header(
+            "#!/bin/sh\n# legacylisten-parser: version=2 mode=server timeout_ms=500\nread x\n",
+        );
+        assert_eq!(plugin.version, 2);
+        assert_eq!(plugin.mode, Mode::Server);
+        assert_eq!(plugin.timeout, Duration::from_millis(500));
+
+        let plugin = header("# legacylisten-parser: version=2 timeout_ms=soon unknown=1");
+        assert_eq!(plugin.version, 2);
+        assert_eq!(plugin.mode, Mode::Oneshot);
+        assert_eq!(plugin.timeout, TIMEOUT);
 
-        None
+        let plugin = header("# legacylisten-parser: version=next");
+        assert_eq!(plugin.version, u32::MAX);
     }
 
-    pub fn new(s: Option<&str>, config: &Arc<ArcConfig>) -> Self
+    #[test]
+    fn without_header_v1()
     {
-        s.and_then(|s| Self::from(s, config))
-            .unwrap_or_else(|| Self {
-                name: config.l10n.get(Message::UnknownTitle),
-                artist: config.l10n.get(Message::UnknownArtist),
-            })
+        let plugin = header("#!/bin/sh\nread x\n");
+        assert_eq!(plugin.version, 1);
+        assert_eq!(plugin.mode, Mode::Oneshot);
+        assert_eq!(plugin.timeout, TIMEOUT);
     }
 
-    // This nursery lint is known to have some false-positives around
-    // destructors.
-    #[allow(clippy::missing_const_for_fn)]
-    pub fn into_tuple(self) -> (String, String)
+    #[test]
+    fn v1_always_oneshot()
     {
-        let Self { name, artist } = self;
+        let plugin = header("# legacylisten-parser: version=1 mode=server");
+        assert_eq!(plugin.version, 1);
+        assert_eq!(plugin.mode, Mode::Oneshot);
 
-        (name, artist)
+        let plugin = header("# legacylisten-parser: mode=server");
+        assert_eq!(plugin.mode, Mode::Oneshot);
     }
 }
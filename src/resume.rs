@@ -0,0 +1,48 @@
+use std::{path::Path, sync::atomic::Ordering, time::Duration};
+
+use crate::{
+    config::{ArcConfig, Config},
+    songs::Song,
+};
+
+impl Song
+{
+    /// Whether the position in this song is remembered if nothing else
+    /// was chosen for it: if it's at least `resume_min_secs` long or
+    /// in one of the `resume_dirs`.
+    pub fn remembers_by_default(&self, config: &ArcConfig) -> bool
+    {
+        let conffile = &config.conffile;
+
+        conffile
+            .resume_dirs
+            .iter()
+            .any(|dir| Path::new(&self.name).starts_with(dir))
+            || (conffile.resume_min_secs != 0
+                && self
+                    .duration
+                    .is_some_and(|duration| duration.as_secs() >= conffile.resume_min_secs))
+    }
+}
+
+impl Config
+{
+    /// Whether the position in the current song is remembered.
+    pub fn remembers_position(&self) -> bool
+    {
+        self.remember.unwrap_or(self.remember_by_default)
+    }
+
+    /// Where the current song has to go on the next time it's
+    /// played; `None` if it starts from the beginning again.
+    pub fn bookmark(&self) -> Option<Duration>
+    {
+        if !self.remembers_position()
+        {
+            return None;
+        }
+
+        self.source
+            .pos_to_duration(self.arc_config.current_pos.load(Ordering::SeqCst))
+    }
+}
@@ -4,12 +4,15 @@
 //! calling the `run()` function.
 
 use std::{
+    mem,
+    path::Path,
     sync::{atomic::Ordering, mpsc},
     thread,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
-use id3::Tag;
+use log::LevelFilter;
+use rodio::Sink;
 use signal_hook::{
     consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2},
     iterator::Signals,
@@ -17,56 +20,345 @@ use signal_hook::{
 use simple_logger::SimpleLogger;
 
 use crate::{
+    alarm::{ramp_up, wait_for_alarm},
+    art::remove_old_art,
     audio::ChannelAudio,
-    config::Config,
+    config::{ArcConfig, Config, MAX_RATE, MIN_RATE},
     err::Error,
-    helpers::take_error,
+    hooks::Event,
     l10n::messages::Message,
+    loudness::{scan, Normalization},
     matcher::{main_match, BigAction},
-    songs::{Song, Songs},
+    scrobble::submit_listens,
+    session::{restore_session, save_session},
+    sleep::check_sleep_timer,
+    songs::{NextSong, Repeat, Song, Songs, DEFAULT_LOUD},
     threads::start_threads,
 };
 
-// Called by songs::choose_random.
-fn handle_song(song: &mut Song, config: &mut Config) -> BigAction
+// How long before the end of a song (or the start of the crossfade)
+// the next one is opened and queued, so that there's no gap between
+// them.
+const PREPARE_SECS: usize = 10;
+
+// Whether it's time to open the next song.  If the length of the
+// current song isn't known (yet), this is only done when it's
+// completely decoded, which usually still is early enough.
+fn should_prepare(config: &Config) -> bool
 {
-    let data_dir = &config.arc_config.conffile.data_dir;
-    let song_path = data_dir.join(song.name.clone());
-    let (tag, tag_option) = take_error(Tag::read_from_path(&song_path));
-    config.tag = Some(tag);
-    config.source = match ChannelAudio::new(&song_path, config.arc_config.clone())
+    if config.next.is_some()
+        || config.quit_after_song
+        || config.pause_after_song
+        || config.arc_config.reading_paused.load(Ordering::SeqCst)
     {
-        Ok(source) => source,
-        Err(e) =>
+        return false;
+    }
+
+    let len = config.arc_config.current_len.load(Ordering::SeqCst);
+    let pos = config.arc_config.current_pos.load(Ordering::SeqCst);
+    let per_sec =
+        config.source.sample_rate as usize * config.arc_config.channels.load(Ordering::SeqCst);
+    let crossfade = config
+        .source
+        .ms_to_samples(config.arc_config.conffile.crossfade_ms);
+
+    (len != 0 && pos + PREPARE_SECS * per_sec + crossfade >= len) || config.source.decoded()
+}
+
+// Whether the switch from the song `current` to `next` is crossfaded
+// (a song isn't crossfaded into itself unless explicitly wanted).
+fn crossfades(config: &Config, current: usize, next: usize) -> bool
+{
+    config.arc_config.conffile.crossfade_ms != 0
+        && (current != next || config.arc_config.conffile.crossfade_repeat)
+}
+
+// Applies everything that is remembered about the song to it.
+fn set_up(audio: &ChannelAudio, song: &Song, path: &Path, config: &ArcConfig)
+{
+    audio.set_equalizer(song.preset(config));
+    audio.set_trim(song.start, song.end);
+
+    if config.conffile.detect_silence && song.start.is_none() && song.end.is_none()
+    {
+        audio.detect_silence(path);
+    }
+}
+
+// Returns whether it worked.
+fn prepare_next(songs: &Songs, current: usize, config: &mut Config) -> bool
+{
+    songs.ensure_next(config);
+
+    let position = config.song_index;
+    let Some(&index) = config.songlist.get(position)
+    else
+    {
+        return false;
+    };
+    let song_path = config
+        .arc_config
+        .conffile
+        .data_dir
+        .join(&songs.songs[index].name);
+
+    // If this fails, it's just opened again the usual way when the
+    // current song ended, which then also reports the error.
+    if let Ok(mut audio) = ChannelAudio::new(
+        &song_path,
+        songs.songs[index].duration,
+        config.arc_config.clone(),
+    )
+    {
+        set_up(&audio, &songs.songs[index], &song_path, &config.arc_config);
+        // When a song is repeated, its bookmark is just where it is
+        // right now.
+        if let Some(pos) = songs.songs[index].bookmark.filter(|_| index != current)
         {
-            config
-                .l10n
-                .write(Message::ReadingSongProblem(&song.name, e));
+            audio.resume_at(pos);
+        }
+        let queued = !crossfades(config, current, index);
 
-            if config.unsuccessful_tries == 255
+        if queued
+        {
+            if let Some(inner) = audio.inner.take()
             {
-                config.l10n.write(Message::TooManyTries);
-                return BigAction::Quit;
+                config.sink.append(inner);
             }
-            config.unsuccessful_tries += 1;
+        }
 
-            config.l10n.write(Message::ChoosingNewSong);
-            return BigAction::Skip;
+        config.next = Some(NextSong {
+            index,
+            position,
+            audio,
+            queued,
+        });
+
+        return true;
+    }
+
+    false
+}
+
+// Starts playing the prepared next song in an own `Sink` when the
+// current one is about to end, with both fading.  If the length of the
+// current song isn't known, it can't be crossfaded, so the next one is
+// just queued as usual when the current song is completely decoded.
+fn crossfade(songs: &Songs, config: &mut Config)
+{
+    // A loop can end right before the crossfade would start.
+    if matches!(config.repeat, Repeat::Section(_, _))
+        || config.next.as_ref().is_none_or(|next| next.queued)
+    {
+        return;
+    }
+
+    let len = config.arc_config.current_len.load(Ordering::SeqCst);
+    let pos = config.arc_config.current_pos.load(Ordering::SeqCst);
+    let crossfade_ms = config.arc_config.conffile.crossfade_ms;
+    let fade_len = config.source.ms_to_samples(crossfade_ms);
+
+    if len != 0 && pos + fade_len >= len
+    {
+        // Without a second sink the next song just follows.
+        let sink = config.new_sink();
+        let fade = config.fade();
+        let Some(next) = config.next.as_mut()
+        else
+        {
+            return;
+        };
+        next.queued = true;
+        let Some(inner) = next.audio.inner.take()
+        else
+        {
+            return;
+        };
+        let Some(sink) = sink
+        else
+        {
+            config.sink.append(inner);
+            return;
+        };
+
+        next.audio.fade_in(next.audio.ms_to_samples(crossfade_ms));
+        config.source.fade_out(pos, len - pos);
+
+        // Both sinks keep the volume of their own song.
+        let song = &songs.songs[next.index];
+        sink.set_volume(song.loud * song.gain(&config.arc_config) * fade);
+        sink.set_speed((config.rate * song.rate).clamp(MIN_RATE, MAX_RATE));
+        sink.append(inner);
+
+        config.fading.push(mem::replace(&mut config.sink, sink));
+    }
+    else if len == 0 && config.source.decoded()
+    {
+        if let Some(next) = config.next.as_mut()
+        {
+            if let Some(inner) = next.audio.inner.take()
+            {
+                config.sink.append(inner);
+            }
+            next.queued = true;
         }
+    }
+}
+
+// The prepared song is useless if in the meantime something decided
+// that another song (or none at all) should follow.
+fn next_is_stale(config: &Config) -> bool
+{
+    config.next.as_ref().is_some_and(|next| {
+        let expected = (
+            config.song_index,
+            config.songlist.get(config.song_index).copied(),
+        );
+
+        config.quit_after_song
+            || config.pause_after_song
+            || expected != (next.position, Some(next.index))
+    })
+}
+
+// Shows which volume the song would have if it were normalized.
+fn propose_volume(song: &Song, config: &Config)
+{
+    if config.arc_config.conffile.normalization == Normalization::Propose
+        && song.loudness.is_some_and(f32::is_finite)
+    {
+        config.l10n.write(Message::ProposedVolume(
+            (DEFAULT_LOUD * song.normalizing_gain(&config.arc_config)).into(),
+        ));
+    }
+}
+
+// Stores the results of the loudness scanner.
+fn receive_loudness(songs: &mut Songs, current: usize, config: &mut Config)
+{
+    while let Ok((index, loudness)) = config.rx_loudness.try_recv()
+    {
+        let song = &mut songs.songs[index];
+        song.loudness = Some(loudness);
+        config.l10n.write(Message::LoudnessMeasured(
+            song.name.clone(),
+            loudness.into(),
+        ));
+
+        if index == current
+        {
+            config.gain = song.gain(&config.arc_config);
+            config.apply_volume();
+            propose_volume(song, config);
+        }
+    }
+}
+
+// Stores the duration of the current song as soon as it's known, so
+// that it doesn't have to be determined again.
+fn remember_duration(songs: &mut Songs, index: usize, config: &mut Config)
+{
+    let song = &mut songs.songs[index];
+
+    if song.duration.is_none()
+    {
+        song.duration = config.source.duration();
+        config.remember_by_default = song.remembers_by_default(&config.arc_config);
+    }
+}
+
+// Cuts off the silence at the beginning and the end of the current
+// song as soon as it's known, unless it was already cut by hand.
+fn receive_silence(config: &mut Config)
+{
+    let Some((start, end)) = config.source.take_silence()
+    else
+    {
+        return;
     };
 
-    config.unsuccessful_tries = 0;
+    if config.start.is_none() && config.end.is_none()
+    {
+        config.start = Some(start);
+        config.end = Some(end);
+        config.settings_changed = true;
+        config.source.set_trim(config.start, config.end);
+        config.l10n.write(Message::SilenceDetected(
+            start.as_secs_f64(),
+            end.as_secs_f64(),
+        ));
+    }
+}
+
+// Reports that a song couldn't be opened and decides whether another
+// one should be tried.
+fn reading_failed(name: &str, e: Error, config: &mut Config) -> BigAction
+{
+    config.l10n.write(Message::ReadingSongProblem(name, e));
 
+    if config.unsuccessful_tries == 255
+    {
+        config.l10n.write(Message::TooManyTries);
+        return BigAction::Quit;
+    }
+    config.unsuccessful_tries += 1;
+
+    config.l10n.write(Message::ChoosingNewSong);
+    BigAction::Skip
+}
+
+// Makes the settings of the song the current ones, so that commands
+// can change them.
+fn load_settings(song: &Song, config: &mut Config)
+{
     config.num = song.num;
     config.loud = song.loud;
+    config.gain = song.gain(&config.arc_config);
+    config.song_rate = song.rate;
+    config.apply_rate();
+    config.equalizer.clone_from(&song.equalizer);
+    config.start = song.start;
+    config.end = song.end;
+    config.remember = song.remember;
+    config.remember_by_default = song.remembers_by_default(&config.arc_config);
+    config.skipped = false;
+    config.settings_changed = false;
+}
 
-    config.sink.append(config.source.inner.take().unwrap());
-    config.sink.set_volume(song.loud);
+// Remembers the current settings for the song if they changed; not
+// every time, since this is done very often.
+fn store_settings(song: &mut Song, config: &mut Config)
+{
+    if !config.settings_changed
+    {
+        return;
+    }
 
-    if let Ok(s) = data_dir
-        .join(song.name.clone())
-        .into_os_string()
-        .into_string()
+    song.num = config.num;
+    song.loud = config.loud;
+    song.rate = config.song_rate;
+    song.start = config.start;
+    song.end = config.end;
+    song.remember = config.remember;
+    song.equalizer.clone_from(&config.equalizer);
+    config.settings_changed = false;
+}
+
+// Remembers where the song is left.  Before it started the position
+// is still the one of the song before and a while after skipping maybe
+// already the one of the next, so this is done right when it's left.
+fn store_bookmark(song: &mut Song, config: &Config)
+{
+    if config.source.started()
+    {
+        song.bookmark = config.bookmark();
+    }
+}
+
+// Tells which song is played now.
+fn announce(song: &Song, song_path: &Path, config: &Config)
+{
+    if let Ok(s) = song_path.to_path_buf().into_os_string().into_string()
     {
         config.l10n.write(Message::PlayingSong(s));
     }
@@ -76,24 +368,128 @@ fn handle_song(song: &mut Song, config: &mut Config) -> BigAction
     }
 
     config.l10n.write(Message::SongLikelihood(song.num));
+    if let Some(pos) = song.bookmark
+    {
+        config.l10n.write(Message::ResumingAt(pos.as_secs_f64()));
+    }
+    propose_volume(song, config);
+}
+
+// Tells the D-Bus thread what's played if it asks.
+fn answer_dbus(song_path: &Path, config: &mut Config)
+{
+    if config.rx_control.try_recv().is_ok()
+    {
+        let _ = config.tx_paused.send(config.paused);
+        // MPRIS wants the pictures as well.
+        config.read_tags();
+        // Not read again, since it might have been changed by
+        // `Command::EditTags`.
+        let metadata = config
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.as_ref().ok())
+            .cloned();
+        let _ = config.tx_path.send((song_path.to_path_buf(), metadata));
+    }
+}
+
+// Called by songs::choose_random.
+fn handle_song(songs: &mut Songs, index: usize, config: &mut Config) -> BigAction
+{
+    let data_dir = &config.arc_config.conffile.data_dir;
+    let song_path = data_dir.join(songs.songs[index].name.clone());
+    config.song_path = Some(song_path.clone());
+    config.index_metadata(&song_path);
+    config.lyrics_line = None;
+
+    let prepared = config.next.take().filter(|next| next.index == index);
+    let already_queued = prepared.as_ref().is_some_and(|next| next.queued);
+
+    config.source = match prepared.map_or_else(
+        || {
+            let audio = ChannelAudio::new(
+                &song_path,
+                songs.songs[index].duration,
+                config.arc_config.clone(),
+            )?;
+            set_up(&audio, &songs.songs[index], &song_path, &config.arc_config);
+            if let Some(pos) = songs.songs[index].bookmark
+            {
+                audio.resume_at(pos);
+            }
+
+            Ok(audio)
+        },
+        |next| Ok(next.audio),
+    )
+    {
+        Ok(source) => source,
+        Err(e) => return reading_failed(&songs.songs[index].name, e, config),
+    };
+
+    config.unsuccessful_tries = 0;
+
+    let song = &songs.songs[index];
+    load_settings(song, config);
+
+    if !already_queued
+    {
+        config.sink.append(config.source.inner.take().unwrap());
+    }
+    config.apply_volume();
+
+    announce(song, &song_path, config);
+    config.run_hooks(Event::SongStarted);
+    let started = SystemTime::now();
 
     config.arc_config.update_dbus.store(true, Ordering::SeqCst);
+    let mut may_prepare = true;
     while !config.sink.empty()
     {
-        match main_match(config)
+        let skipped = config.skipped;
+        let action = match main_match(config)
         {
-            BigAction::Nothing =>
-            {
-                song.num = config.num;
-                song.loud = config.loud;
-            }
-            x => return x,
+            BigAction::Nothing => check_sleep_timer(config),
+            x => x,
+        };
+        store_settings(&mut songs.songs[index], config);
+        let left = !matches!(action, BigAction::Nothing);
+        if !skipped && (config.skipped || left)
+        {
+            store_bookmark(&mut songs.songs[index], config);
         }
-        if config.rx_control.try_recv().is_ok()
+        if left
         {
-            let _ = config.tx_paused.send(config.paused);
-            let _ = config.tx_path.send((song_path.clone(), tag_option.clone()));
+            config.record_listen(started);
+            return action;
         }
+        if config
+            .next
+            .as_ref()
+            .is_some_and(|next| next.audio.started())
+        {
+            // The next song already plays.
+            break;
+        }
+        // If cancelling fails, the next song just started and the
+        // next round ends this one.
+        if next_is_stale(config) && config.next.as_ref().is_some_and(|next| next.audio.cancel())
+        {
+            config.next = None;
+        }
+        if may_prepare && should_prepare(config)
+        {
+            may_prepare = prepare_next(songs, index, config);
+        }
+        crossfade(songs, config);
+        ramp_up(config);
+        receive_loudness(songs, index, config);
+        remember_duration(songs, index, config);
+        receive_silence(config);
+        config.show_lyrics();
+        config.fading.retain(|sink| !sink.empty());
+        answer_dbus(&song_path, config);
         if config.arc_config.reading_paused.load(Ordering::SeqCst)
         {
             config.l10n.write(Message::SignalPaused);
@@ -101,6 +497,17 @@ fn handle_song(song: &mut Song, config: &mut Config) -> BigAction
         // To prevent busy loop
         thread::sleep(Duration::from_micros(1));
     }
+    store_settings(&mut songs.songs[index], config);
+    remember_duration(songs, index, config);
+    // A song that was played to its end starts from the beginning the
+    // next time.
+    if !config.skipped
+    {
+        songs.songs[index].bookmark = None;
+        config.run_hooks(Event::SongFinished);
+    }
+    config.record_listen(started);
+    config.playing = None;
 
     BigAction::Nothing
 }
@@ -116,8 +523,10 @@ fn handle_pausely(config: &mut Config) -> bool
     {
         config.l10n.write(Message::RequestedPause);
         config.sink.pause();
+        config.fading.iter().for_each(Sink::pause);
         config.paused = true;
         config.pause_after_song = false;
+        config.run_hooks(Event::Paused);
     }
     while config
         .arc_config
@@ -142,7 +551,12 @@ fn handle_pausely(config: &mut Config) -> bool
 /// actually can be passed down.
 pub fn run() -> Result<(), Error>
 {
-    SimpleLogger::new().init().unwrap();
+    // lofty logs every file it opens and ureq every connection.
+    SimpleLogger::new()
+        .with_module_level("lofty", LevelFilter::Warn)
+        .with_module_level("ureq", LevelFilter::Warn)
+        .init()
+        .unwrap();
 
     // Initializing some channels for communication between some
     // far-away parts.  Better than the original globals, but still
@@ -155,6 +569,9 @@ pub fn run() -> Result<(), Error>
     let mut config = Config::new(rx_control, tx_paused, tx_path)?;
     // Reading the likelihoods and volumes of all songs.
     let mut songs = Songs::read(config.arc_config.clone(), config.l10n)?;
+    // What was played the last time.
+    restore_session(&mut songs, &mut config);
+    remove_old_art(&config.arc_config);
     // Copied to make the borrowck happy.
     let l10n = config.l10n;
 
@@ -169,6 +586,29 @@ pub fn run() -> Result<(), Error>
         config.l10n,
     );
 
+    // Only continues when the alarm goes off (if there's one).
+    if matches!(wait_for_alarm(&mut config), BigAction::Quit)
+    {
+        return Ok(());
+    }
+
+    // Measures the loudness of new songs in the background; not
+    // before the alarm, so that nothing is read while waiting for it.
+    if config.arc_config.conffile.normalization != Normalization::Off
+    {
+        let unmeasured = songs.unmeasured();
+        let tx = config.tx_loudness.clone();
+        let arc_config = config.arc_config.clone();
+        thread::spawn(move || scan(unmeasured, &tx, &arc_config));
+    }
+
+    // Submits the listens to ListenBrainz in the background.
+    if config.arc_config.conffile.listenbrainz_token.is_some()
+    {
+        let arc_config = config.arc_config.clone();
+        thread::spawn(move || submit_listens(&arc_config));
+    }
+
     loop
     {
         // There are multiple ways of pausing; handle all of them.
@@ -192,6 +632,11 @@ pub fn run() -> Result<(), Error>
         }
     }
 
+    save_session(&songs, &config);
+    if let Ok(mut listens) = config.arc_config.listens.lock()
+    {
+        listens.flush(&config.arc_config, true);
+    }
     config
         .l10n
         .write(Message::TotalPlayingLikelihood(songs.total_likelihood()));
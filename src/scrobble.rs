@@ -0,0 +1,558 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use ureq::AgentBuilder;
+
+use crate::{
+    config::{ArcConfig, Config},
+    csv::{non_empty, parse_duration, Csv},
+    err::Error,
+    l10n::messages::Message,
+};
+
+// Lies next to `songs.csv`.
+const LISTENS_FILE: &str = "listens.csv";
+// A song counts as listened to after half of it, but at most after
+// this long.
+const LISTEN_MAX: Duration = Duration::from_secs(4 * 60);
+// How many listens are submitted at once.
+const BATCH: usize = 100;
+// After failing, submitting is tried again after the first and then
+// twice as long every time, up to the second.
+const MIN_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A song that was played long enough to count as a listen.
+#[derive(Clone, Debug)]
+pub struct Listen
+{
+    // When it started, in seconds since the epoch.
+    listened_at: u64,
+    song: PathBuf,
+    // ListenBrainz needs at least them.
+    title: String,
+    artist: String,
+    album: Option<String>,
+    track: Option<u32>,
+    duration: Option<Duration>,
+}
+
+/// The listens which weren't submitted yet.  They are kept on disk,
+/// so that they survive restarts and times without network.
+#[derive(Debug, Default)]
+pub struct Listens
+{
+    listens: Vec<Listen>,
+    // Whether they changed since they were saved; while reading is
+    // paused (`SIGUSR1`) the disk isn't touched.
+    changed: bool,
+}
+
+impl Listen
+{
+    fn to_json(&self) -> Value
+    {
+        let mut additional_info = json!({
+            "media_player": "legacylisten",
+            "submission_client": "legacylisten",
+            "submission_client_version": env!("CARGO_PKG_VERSION"),
+        });
+        if let Some(track) = self.track
+        {
+            additional_info["tracknumber"] = json!(track);
+        }
+        if let Some(duration) = self.duration
+        {
+            additional_info["duration_ms"] = json!(duration.as_millis());
+        }
+
+        let mut track_metadata = json!({
+            "artist_name": self.artist,
+            "track_name": self.title,
+            "additional_info": additional_info,
+        });
+        if let Some(album) = &self.album
+        {
+            track_metadata["release_name"] = json!(album);
+        }
+
+        json!({
+            "listened_at": self.listened_at,
+            "track_metadata": track_metadata,
+        })
+    }
+}
+
+impl Listens
+{
+    /// Reads the listens which weren't submitted the last time.
+    pub fn read(config_dir: &Path) -> Self
+    {
+        let Ok(csv) = Csv::new(config_dir.join(LISTENS_FILE))
+        else
+        {
+            return Self::default();
+        };
+
+        let listens = csv
+            .entries
+            .into_iter()
+            .filter(|entry| entry.len() == 7)
+            .filter_map(|mut entry| {
+                Some(Listen {
+                    listened_at: entry[0].parse().ok()?,
+                    song: PathBuf::from(mem::take(&mut entry[1])),
+                    title: non_empty(&mut entry[2])?,
+                    artist: non_empty(&mut entry[3])?,
+                    album: non_empty(&mut entry[4]),
+                    track: entry[5].parse().ok(),
+                    duration: parse_duration(entry.get(6)),
+                })
+            })
+            .collect();
+
+        Self {
+            listens,
+            changed: false,
+        }
+    }
+
+    fn save(&mut self, config: &ArcConfig, quitting: bool) -> Result<(), Error>
+    {
+        // When quitting they have to be saved anyway, like `songs.csv`.
+        if !self.changed || (config.reading_paused.load(Ordering::SeqCst) && !quitting)
+        {
+            return Ok(());
+        }
+
+        let entries = self
+            .listens
+            .iter()
+            .map(|listen| {
+                vec![
+                    listen.listened_at.to_string(),
+                    listen.song.to_string_lossy().into_owned(),
+                    listen.title.clone(),
+                    listen.artist.clone(),
+                    listen.album.clone().unwrap_or_default(),
+                    listen.track.map(|x| x.to_string()).unwrap_or_default(),
+                    listen
+                        .duration
+                        .map(|x| x.as_secs_f64().to_string())
+                        .unwrap_or_default(),
+                ]
+            })
+            .collect();
+
+        // Written into another file first, so that the listens aren't
+        // lost if `legacylisten` is stopped in between.
+        let path = config.config_dir.join(LISTENS_FILE);
+        let tmp = path.with_extension("csv.tmp");
+        File::create(&tmp)?.write_all(Csv { entries }.to_string().as_bytes())?;
+        fs::rename(tmp, path)?;
+        self.changed = false;
+
+        Ok(())
+    }
+
+    /// Saves them if they changed and it's possible.
+    pub fn flush(&mut self, config: &ArcConfig, quitting: bool)
+    {
+        if let Err(err) = self.save(config, quitting)
+        {
+            config
+                .l10n
+                .write(Message::ListensNotSaved(format!("{err:?}")));
+        }
+    }
+}
+
+// What ListenBrainz expects at `submit-listens`.
+fn payload(listens: &[Listen]) -> Value
+{
+    json!({
+        "listen_type": if listens.len() == 1 { "single" } else { "import" },
+        "payload": listens.iter().map(Listen::to_json).collect::<Vec<_>>(),
+    })
+}
+
+// Submits the listens to the ListenBrainz API at `url` (e.g.
+// `https://api.listenbrainz.org`).
+fn submit(url: &str, token: &str, listens: &[Listen]) -> Result<(), Error>
+{
+    AgentBuilder::new()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .post(&format!("{}/1/submit-listens", url.trim_end_matches('/')))
+        .set("Authorization", &format!("Token {token}"))
+        .send_json(payload(listens))?;
+
+    Ok(())
+}
+
+// What became of the listens at the start of the queue.
+#[derive(Debug)]
+enum Outcome
+{
+    // It isn't time to try again yet.
+    Waiting,
+    // That many were submitted.
+    Submitted(usize),
+    // The batch was rejected, so they are tried one by one.
+    Split,
+    // The first one was rejected on its own and is dropped.
+    Rejected,
+    // The server can't be reached or has problems; `true` the first
+    // time in a row.
+    Failed(Error, bool),
+}
+
+// When and how much is submitted next.
+#[derive(Debug)]
+struct Submitter
+{
+    url: String,
+    token: String,
+    backoff: Duration,
+    next_try: Instant,
+    // Rejected listens are submitted one by one, so that only the
+    // wrong one is dropped.
+    batch: usize,
+}
+
+impl Submitter
+{
+    fn new(url: &str, token: &str) -> Self
+    {
+        Self {
+            url: url.to_string(),
+            token: token.to_string(),
+            backoff: MIN_BACKOFF,
+            next_try: Instant::now(),
+            batch: BATCH,
+        }
+    }
+
+    // Tries to submit the start of `queue`.
+    fn step(&mut self, queue: &[Listen]) -> Outcome
+    {
+        if queue.is_empty() || Instant::now() < self.next_try
+        {
+            return Outcome::Waiting;
+        }
+
+        let listens = &queue[..queue.len().min(self.batch)];
+        match submit(&self.url, &self.token, listens)
+        {
+            Ok(()) =>
+            {
+                self.backoff = MIN_BACKOFF;
+                self.batch = BATCH;
+                Outcome::Submitted(listens.len())
+            }
+            Err(Error::Http(err)) if matches!(*err, ureq::Error::Status(400, _)) =>
+            {
+                self.batch = 1;
+                if listens.len() == 1
+                {
+                    Outcome::Rejected
+                }
+                else
+                {
+                    Outcome::Split
+                }
+            }
+            Err(err) =>
+            {
+                let first = self.backoff == MIN_BACKOFF;
+                self.next_try = Instant::now() + self.backoff;
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Outcome::Failed(err, first)
+            }
+        }
+    }
+}
+
+/// Submits the listens in the background whenever there are some and
+/// the endpoint can be reached.
+pub fn submit_listens(config: &Arc<ArcConfig>)
+{
+    let Some(token) = &config.conffile.listenbrainz_token
+    else
+    {
+        return;
+    };
+    let mut submitter = Submitter::new(&config.conffile.listenbrainz_url, token);
+
+    loop
+    {
+        // Is there any way to make that better than a poll loop?
+        thread::sleep(Duration::from_secs(1));
+
+        let listens = match config.listens.lock()
+        {
+            Ok(mut listens) =>
+            {
+                // Maybe it couldn't be saved while reading was
+                // paused.
+                listens.flush(config, false);
+                listens.listens[..listens.listens.len().min(BATCH)].to_vec()
+            }
+            Err(_) => return,
+        };
+
+        let done = match submitter.step(&listens)
+        {
+            Outcome::Waiting | Outcome::Split => 0,
+            Outcome::Submitted(len) => len,
+            Outcome::Rejected =>
+            {
+                config.l10n.write(Message::ListenDropped(
+                    listens[0].song.to_string_lossy().into_owned(),
+                ));
+                1
+            }
+            Outcome::Failed(err, first) =>
+            {
+                // Only once until it works again.
+                if first
+                {
+                    config
+                        .l10n
+                        .write(Message::SubmittingListensFailed(err.to_string()));
+                }
+                0
+            }
+        };
+
+        if done != 0
+        {
+            if let Ok(mut queue) = config.listens.lock()
+            {
+                // New ones are only appended in the meantime.
+                queue.listens.drain(..done);
+                queue.changed = true;
+                queue.flush(config, false);
+            }
+        }
+    }
+}
+
+impl Config
+{
+    /// Queues the current song as a listen if it was played long
+    /// enough (half of it or four minutes).
+    pub fn record_listen(&self, started: SystemTime)
+    {
+        if self.arc_config.conffile.listenbrainz_token.is_none()
+        {
+            return;
+        }
+        let Some(song) = self.song_path.clone()
+        else
+        {
+            return;
+        };
+
+        let duration = self.source.duration();
+        let needed = duration.map_or(LISTEN_MAX, |duration| (duration / 2).min(LISTEN_MAX));
+        // Seeking forward doesn't count.
+        if self.source.played().unwrap_or_default() < needed
+        {
+            return;
+        }
+
+        // The plugins can take long, so they are asked only here and
+        // not while playing or every time submitting is tried.
+        let metadata = self.arc_config.indexed_parsed(&song).unwrap_or_default();
+        let (Some(title), Some(artist)) = (metadata.title, metadata.artist)
+        else
+        {
+            self.l10n
+                .write(Message::ListenDropped(song.to_string_lossy().into_owned()));
+            return;
+        };
+
+        let listen = Listen {
+            listened_at: started
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default(),
+            song,
+            title,
+            artist,
+            album: metadata.album,
+            track: metadata.track,
+            duration,
+        };
+
+        if let Ok(mut listens) = self.arc_config.listens.lock()
+        {
+            listens.listens.push(listen);
+            listens.changed = true;
+            listens.flush(&self.arc_config, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+    };
+
+    use super::*;
+
+    // Answers one request after another with these status codes and
+    // sends on what was requested (the head and the body).
+    fn server(statuses: Vec<u16>) -> (String, Receiver<(String, Vec<u8>)>)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            for status in statuses
+            {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut head = String::new();
+                let mut len = 0;
+                loop
+                {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim_end().is_empty()
+                    {
+                        break;
+                    }
+                    if let Some((key, value)) = line.split_once(':')
+                    {
+                        if key.eq_ignore_ascii_case("content-length")
+                        {
+                            len = value.trim().parse().unwrap();
+                        }
+                    }
+                    head.push_str(&line);
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                tx.send((head, body)).unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn listen(i: u64) -> Listen
+    {
+        Listen {
+            listened_at: 1_700_000_000 + i,
+            song: PathBuf::from(format!("song {i}.mp3")),
+            title: format!("Title {i}"),
+            artist: "Artist".to_string(),
+            album: None,
+            track: Some(i as u32),
+            duration: Some(Duration::from_secs(180)),
+        }
+    }
+
+    #[test]
+    fn submits_batch()
+    {
+        let (url, requests) = server(vec![200]);
+        let mut submitter = Submitter::new(&url, "secret");
+        let listens = [listen(1), listen(2)];
+
+        assert!(matches!(submitter.step(&listens), Outcome::Submitted(2)));
+
+        let (head, body) = requests.recv().unwrap();
+        assert!(head.starts_with("POST /1/submit-listens "));
+        assert!(head.to_lowercase().contains("authorization: token secret"));
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["listen_type"], "import");
+        assert_eq!(body["payload"].as_array().unwrap().len(), 2);
+        assert_eq!(body["payload"][1]["listened_at"], 1_700_000_002);
+        assert_eq!(
+            body["payload"][1]["track_metadata"]["track_name"],
+            "Title 2"
+        );
+        assert_eq!(
+            body["payload"][1]["track_metadata"]["artist_name"],
+            "Artist"
+        );
+    }
+
+    #[test]
+    fn drops_rejected_listen()
+    {
+        let (url, requests) = server(vec![400, 400, 200]);
+        let mut submitter = Submitter::new(&url, "secret");
+        let listens = [listen(1), listen(2)];
+
+        assert!(matches!(submitter.step(&listens), Outcome::Split));
+        assert!(matches!(submitter.step(&listens), Outcome::Rejected));
+        assert!(matches!(
+            submitter.step(&listens[1..]),
+            Outcome::Submitted(1)
+        ));
+
+        let bodies: Vec<Value> = requests
+            .iter()
+            .take(3)
+            .map(|(_, body)| serde_json::from_slice(&body).unwrap())
+            .collect();
+        assert_eq!(bodies[0]["payload"].as_array().unwrap().len(), 2);
+        assert_eq!(bodies[1]["listen_type"], "single");
+        assert_eq!(
+            bodies[1]["payload"][0]["track_metadata"]["track_name"],
+            "Title 1"
+        );
+        assert_eq!(
+            bodies[2]["payload"][0]["track_metadata"]["track_name"],
+            "Title 2"
+        );
+    }
+
+    #[test]
+    fn backs_off_on_server_errors()
+    {
+        let (url, requests) = server(vec![503, 503]);
+        let mut submitter = Submitter::new(&url, "secret");
+        let listens = [listen(1)];
+
+        assert!(matches!(submitter.step(&listens), Outcome::Failed(_, true)));
+        assert_eq!(submitter.backoff, MIN_BACKOFF * 2);
+        // Nothing is sent until the backoff is over.
+        assert!(matches!(submitter.step(&listens), Outcome::Waiting));
+        requests.recv().unwrap();
+        assert!(requests.try_recv().is_err());
+
+        submitter.next_try = Instant::now();
+        assert!(matches!(
+            submitter.step(&listens),
+            Outcome::Failed(_, false)
+        ));
+        assert_eq!(submitter.backoff, MIN_BACKOFF * 4);
+        requests.recv().unwrap();
+    }
+}
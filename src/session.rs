@@ -0,0 +1,119 @@
+use std::{collections::HashMap, fs::File, io::Write, sync::atomic::Ordering};
+
+use crate::{
+    config::Config,
+    csv::{parse_duration, Csv},
+    err::Error,
+    l10n::messages::Message,
+    songs::Songs,
+};
+
+// Lies next to `songs.csv`.
+const SESSION_FILE: &str = "session.csv";
+
+// The songs are stored by name, since their indices change when new
+// ones are found.
+fn write_session(songs: &Songs, config: &Config) -> Result<(), Error>
+{
+    let mut entries: Vec<_> = config
+        .songlist
+        .iter()
+        .map(|&index| vec!["song".to_string(), songs.songs[index].name.clone()])
+        .collect();
+
+    if let Some(position) = config.playing
+    {
+        let pos = config
+            .source
+            .started()
+            .then(|| {
+                config
+                    .source
+                    .pos_to_duration(config.arc_config.current_pos.load(Ordering::SeqCst))
+            })
+            .flatten()
+            .unwrap_or_default();
+
+        entries.push(vec![
+            "current".to_string(),
+            position.to_string(),
+            pos.as_secs_f64().to_string(),
+        ]);
+    }
+
+    File::create(config.arc_config.config_dir.join(SESSION_FILE))?
+        .write_all(Csv { entries }.to_string().as_bytes())?;
+
+    Ok(())
+}
+
+/// Saves the history of played songs and where the current one was
+/// interrupted.
+pub fn save_session(songs: &Songs, config: &Config)
+{
+    if let Err(err) = write_session(songs, config)
+    {
+        config
+            .l10n
+            .write(Message::SessionNotSaved(format!("{err:?}")));
+    }
+}
+
+/// Restores what `save_session` saved, so that `r` still works after
+/// a restart.  With `continue_song` the interrupted song is played
+/// first again from where it was.
+pub fn restore_session(songs: &mut Songs, config: &mut Config)
+{
+    let Ok(csv) = Csv::new(config.arc_config.config_dir.join(SESSION_FILE))
+    else
+    {
+        // There's none the first time.
+        return;
+    };
+
+    // Where every stored song is now in `songlist`; songs which don't
+    // exist anymore are left out.
+    let mut positions = vec![];
+    let mut current = None;
+    // The history can be long, so the songs aren't searched by name
+    // for every line.
+    let indices: HashMap<_, _> = songs
+        .songs
+        .iter()
+        .enumerate()
+        .map(|(index, song)| (song.name.as_str(), index))
+        .collect();
+
+    for line in &csv.entries
+    {
+        match (line.first().map(String::as_str), line.len())
+        {
+            (Some("song"), 2) =>
+            {
+                let index = indices.get(line[1].as_str()).copied();
+                positions.push(index.map(|_| config.songlist.len()));
+                config.songlist.extend(index);
+            }
+            (Some("current"), 3) =>
+            {
+                current = line[1]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|position| positions.get(position).copied().flatten())
+                    .map(|position| (position, parse_duration(line.get(2))));
+            }
+            _ =>
+            {}
+        }
+    }
+
+    config.song_index = config.songlist.len();
+    if config.arc_config.conffile.continue_song
+    {
+        if let Some((position, pos)) = current
+        {
+            config.song_index = position;
+            songs.songs[config.songlist[position]].bookmark = pos.filter(|pos| !pos.is_zero());
+        }
+    }
+}
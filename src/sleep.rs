@@ -0,0 +1,92 @@
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::{commands::Command, config::Config, matcher::BigAction};
+
+// Over how much of the end the volume is faded out.
+const FADE: Duration = Duration::from_secs(60);
+
+/// Stops playing at a certain time (like when falling asleep while
+/// listening).
+#[derive(Clone, Copy, Debug)]
+pub struct SleepTimer
+{
+    pub end: Instant,
+    // Whether to pause or to quit at the end.
+    pub pause: bool,
+}
+
+impl SleepTimer
+{
+    pub fn new(minutes: usize, pause: bool) -> Self
+    {
+        Self {
+            end: Instant::now() + Duration::from_secs(minutes as u64 * 60),
+            pause,
+        }
+    }
+
+    pub fn left(&self) -> Duration
+    {
+        self.end.saturating_duration_since(Instant::now())
+    }
+
+    /// What the volume has to be multiplied with right now; it goes
+    /// down to zero over the last minute.
+    pub fn fade(&self) -> f32
+    {
+        (self.left().as_secs_f32() / FADE.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Fades out and pauses or quits when the sleep timer has run out.
+/// This is done exactly like with the commands, so that everything
+/// is saved as usual.
+pub fn check_sleep_timer(config: &mut Config) -> BigAction
+{
+    let Some(timer) = config.sleep
+    else
+    {
+        return BigAction::Nothing;
+    };
+
+    let left = timer.left();
+    if !left.is_zero()
+    {
+        if left < FADE
+        {
+            config.apply_volume();
+        }
+        return BigAction::Nothing;
+    }
+
+    let action = if timer.pause
+    {
+        Command::Pause.get_handler()(config)
+    }
+    else
+    {
+        Command::Quit.get_handler()(config)
+    };
+    config.set_sleep_timer(None);
+    // Only after pausing, so that it's not loud again for a moment.
+    config.apply_volume();
+
+    action
+}
+
+impl Config
+{
+    /// Starts, replaces or (with `None`) cancels the sleep timer.
+    pub fn set_sleep_timer(&mut self, timer: Option<SleepTimer>)
+    {
+        self.sleep = timer;
+        if let Ok(mut end) = self.arc_config.sleep_end.lock()
+        {
+            *end = timer.map(|timer| timer.end);
+        }
+        self.arc_config.update_dbus.store(true, Ordering::SeqCst);
+    }
+}
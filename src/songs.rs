@@ -1,9 +1,18 @@
-use std::{cmp::Ordering, fs::File, io::Write, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{Arc, PoisonError},
+    time::Duration,
+};
 
 use rand::random;
 use walkdir::WalkDir;
 
 use crate::{
+    audio::ChannelAudio,
     config::{ArcConfig, Config},
     csv::Csv,
     err::Error,
@@ -11,12 +20,32 @@ use crate::{
     matcher::BigAction,
 };
 
+/// The volume a newly found song gets.
+pub const DEFAULT_LOUD: f32 = 0.1;
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Repeat
 {
     Not,
     Once,
     Always,
+    /// Only the part of the current song between these two positions.
+    Section(Duration, Duration),
+}
+
+/// A song which is already opened and appended to the `Sink` while
+/// the one before it is still playing, so that there's no gap between
+/// them.
+pub struct NextSong
+{
+    /// Index into `Songs::songs`.
+    pub index: usize,
+    /// Index into `Config::songlist`.
+    pub position: usize,
+    pub audio: ChannelAudio,
+    /// Whether it's already appended to `Config::sink`; when
+    /// crossfading it gets an own `Sink` only when the fade starts.
+    pub queued: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -25,6 +54,28 @@ pub struct Song
     pub name: String,
     pub num: u32,
     pub loud: f32,
+    /// The playback rate of this song (multiplied with the global
+    /// one).
+    pub rate: f32,
+    /// The measured integrated loudness in LUFS; `None` if it wasn't
+    /// measured yet and NaN if it couldn't be measured.
+    pub loudness: Option<f32>,
+    /// Stored, so that it has to be determined only once.
+    pub duration: Option<Duration>,
+    /// The name of the equalizer preset; `None` if the default one
+    /// is used.
+    pub equalizer: Option<String>,
+    /// Where the song starts and ends when it's played; `None` if at
+    /// its real beginning or end.  If the silence is detected, `start`
+    /// is always set afterwards, so that it isn't done again.
+    pub start: Option<Duration>,
+    pub end: Option<Duration>,
+    /// Where playing goes on the next time; only set if the position
+    /// in this song is remembered.
+    pub bookmark: Option<Duration>,
+    /// Whether the position in this song is remembered; `None` if
+    /// that's decided by the conffile (see `remembers_by_default`).
+    pub remember: Option<bool>,
 }
 
 #[derive(Clone)]
@@ -74,6 +125,17 @@ impl Drop for Songs
                 eprintln!("{}", s);
             }
         }
+
+        // Songs whose tags were edited.
+        if let Ok(mut library) = self.config.library.lock()
+        {
+            if let Err(err) = library.save(&self.config.config_dir)
+            {
+                self.l10n_helper
+                    .l10n
+                    .write(Message::LibraryNotSaved(format!("{err:?}")));
+            }
+        }
     }
 }
 
@@ -99,6 +161,19 @@ impl Songs
         let mut songs = Csv::new(config.config_dir.join("songs.csv"))?
             .get_songs(config, l10n)
             .ok_or(Error::MalformattedSongsCsv)?;
+        // Looking the songs up by name would be too slow for big
+        // libraries otherwise.
+        let mut known: HashMap<_, _> = songs
+            .songs
+            .iter()
+            .enumerate()
+            .map(|(index, song)| (song.name.clone(), index))
+            .collect();
+        let mut library = songs
+            .config
+            .library
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
 
         for file in config_dir_handle(&songs.config)?
         {
@@ -115,40 +190,86 @@ impl Songs
                 .to_string_lossy()
                 .into_owned();
 
-            if !songs.songs.iter().any(|x| x.name == filename)
+            let changed = library.update(&filename, file.path());
+            let duration = library.get(&filename).and_then(|entry| entry.duration);
+
+            if let Some(&index) = known.get(&filename)
+            {
+                let song = &mut songs.songs[index];
+                // The duration from the tags is only a guess, so a
+                // measured one is kept as long as the file is the same.
+                if changed || song.duration.is_none()
+                {
+                    song.duration = duration;
+                }
+            }
+            else
             {
                 l10n.write(Message::NewSongFound(filename.clone()));
 
+                known.insert(filename.clone(), songs.songs.len());
                 songs.songs.push(Song {
                     name: filename,
                     num: 10,
-                    loud: 0.1,
+                    loud: DEFAULT_LOUD,
+                    rate: 1.0,
+                    loudness: None,
+                    duration,
+                    equalizer: None,
+                    start: None,
+                    end: None,
+                    bookmark: None,
+                    remember: None,
                 });
             }
         }
 
+        library.retain(|name| known.contains_key(name));
+        if let Err(err) = library.save(&songs.config.config_dir)
+        {
+            l10n.write(Message::LibraryNotSaved(format!("{err:?}")));
+        }
+        drop(library);
+
         Ok(songs)
     }
 
-    pub fn choose_random<F>(&mut self, config: &mut Config, mut f: F, l10n: L10n) -> BigAction
-    where
-        F: FnMut(&mut Song, &mut Config) -> BigAction,
+    /// All songs whose loudness wasn't measured yet, together with
+    /// their index.
+    #[must_use]
+    pub fn unmeasured(&self) -> Vec<(usize, String)>
     {
-        let total = self.total_likelihood();
-
-        l10n.write(Message::TotalPlayingLikelihood(total));
+        self.songs
+            .iter()
+            .enumerate()
+            .filter(|(_, song)| song.loudness.is_none())
+            .map(|(index, song)| (index, song.name.clone()))
+            .collect()
+    }
 
-        if total == 0
-        {
-            l10n.write(Message::NoSongs);
-            return BigAction::Quit;
-        }
+    /// Makes sure that `config.songlist[config.song_index]` exists
+    /// (i.e. that it's determined which song is played next) by
+    /// choosing a new one at random if necessary.
+    pub fn ensure_next(&self, config: &mut Config)
+    {
+        // If none of the songs that should be chosen can be, any is.
+        let only = config.only_songs.as_deref().filter(|dir| {
+            self.songs
+                .iter()
+                .any(|song| song.num != 0 && Path::new(&song.name).starts_with(dir))
+        });
+        let chosen = |song: &&Song| only.is_none_or(|dir| Path::new(&song.name).starts_with(dir));
+        let total: u32 = self.songs.iter().filter(chosen).map(|x| x.num).sum();
 
-        if config.songlist.len() == config.song_index
+        if config.songlist.len() == config.song_index && total != 0
         {
             let mut song_number = (random::<u64>() % total as u64) as _;
 
-            for (pos, song) in self.songs.iter_mut().enumerate()
+            for (pos, song) in self
+                .songs
+                .iter()
+                .enumerate()
+                .filter(|(_, song)| chosen(song))
             {
                 if song.num >= song_number
                 {
@@ -158,10 +279,28 @@ impl Songs
                 song_number -= song.num;
             }
         }
+    }
+
+    pub fn choose_random<F>(&mut self, config: &mut Config, mut f: F, l10n: L10n) -> BigAction
+    where
+        F: FnMut(&mut Self, usize, &mut Config) -> BigAction,
+    {
+        let total = self.total_likelihood();
+
+        l10n.write(Message::TotalPlayingLikelihood(total));
+
+        if total == 0
+        {
+            l10n.write(Message::NoSongs);
+            return BigAction::Quit;
+        }
+
+        self.ensure_next(config);
 
         let index = config.songlist[config.song_index];
+        config.playing = Some(config.song_index);
 
-        if config.repeat != Repeat::Not
+        if matches!(config.repeat, Repeat::Once | Repeat::Always)
         {
             self.songs[index].num = (self.songs[index].num as i64
                 + config.arc_config.conffile.repeat_bonus)
@@ -188,7 +327,8 @@ impl Songs
         match config.repeat
         {
             Repeat::Not => config.song_index += 1,
-            Repeat::Once =>
+            // The loop belongs to the song that's over now.
+            Repeat::Once | Repeat::Section(_, _) =>
             {
                 config.repeat = Repeat::Not;
                 config.song_index += 1;
@@ -197,7 +337,7 @@ impl Songs
             {}
         }
 
-        f(&mut self.songs[index], config)
+        f(self, index, config)
     }
 }
 
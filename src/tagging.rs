@@ -0,0 +1,313 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead},
+    path::Path,
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
+};
+
+use id3::{TagLike, Timestamp, Version};
+use lofty::{
+    ape::ApeFile,
+    config::{ParseOptions, WriteOptions},
+    error::{ErrorKind, LoftyError},
+    file::{AudioFile, FileType},
+    flac::FlacFile,
+    mp4::Mp4File,
+    musepack::MpcFile,
+    ogg::{OpusFile, SpeexFile, VorbisFile},
+    probe::Probe,
+    tag::Accessor,
+    wavpack::WavPackFile,
+};
+
+use crate::{
+    config::{ArcConfig, Config},
+    err::Error,
+    l10n::{messages::Message, L10n},
+};
+
+/// What was typed for `Command::EditTags`; `None` keeps the old
+/// value.
+#[derive(Clone, Debug)]
+pub struct TagEdit
+{
+    // `ArcConfig::active_song` when the editing started, so that a
+    // song that started in the meantime isn't changed.
+    song: usize,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    track: Option<u32>,
+    year: Option<u32>,
+}
+
+impl TagEdit
+{
+    const fn is_empty(&self) -> bool
+    {
+        self.title.is_none()
+            && self.artist.is_none()
+            && self.album.is_none()
+            && self.track.is_none()
+            && self.year.is_none()
+    }
+}
+
+// Asks until something valid or nothing is typed.
+fn ask<T: FromStr>(message: Message, l10n: L10n) -> Option<T>
+{
+    l10n.write(message);
+
+    loop
+    {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line).ok()?;
+        let line = line.trim();
+
+        if line.is_empty()
+        {
+            return None;
+        }
+        match line.parse()
+        {
+            Ok(value) => return Some(value),
+            Err(_) => l10n.write(Message::NotANumber(line.to_string())),
+        }
+    }
+}
+
+/// Asks for the new tags of the current song.  This is done by the
+/// input thread, since it has to read whole lines.
+pub fn read_tag_edit(config: &Arc<ArcConfig>, l10n: L10n) -> TagEdit
+{
+    let song = config.active_song.load(Ordering::SeqCst);
+
+    // The rest of the line with the command.
+    let _ = io::stdin().lock().read_line(&mut String::new());
+
+    TagEdit {
+        song,
+        title: ask(Message::EditTitle, l10n),
+        artist: ask(Message::EditArtist, l10n),
+        album: ask(Message::EditAlbum, l10n),
+        track: ask(Message::EditTrack, l10n),
+        year: ask(Message::EditYear, l10n),
+    }
+}
+
+impl TagEdit
+{
+    // Only what was typed is changed, so that everything else in the
+    // tag stays as it is.
+    fn apply<T: Accessor>(&self, tag: &mut T)
+    {
+        if let Some(title) = &self.title
+        {
+            tag.set_title(title.clone());
+        }
+        if let Some(artist) = &self.artist
+        {
+            tag.set_artist(artist.clone());
+        }
+        if let Some(album) = &self.album
+        {
+            tag.set_album(album.clone());
+        }
+        if let Some(track) = self.track
+        {
+            tag.set_track(track);
+        }
+        if let Some(year) = self.year
+        {
+            tag.set_year(year);
+        }
+    }
+
+    // The same for an ID3 tag of the `id3` crate.
+    fn apply_id3(&self, tag: &mut id3::Tag)
+    {
+        if let Some(title) = &self.title
+        {
+            tag.set_title(title.clone());
+        }
+        if let Some(artist) = &self.artist
+        {
+            tag.set_artist(artist.clone());
+        }
+        if let Some(album) = &self.album
+        {
+            tag.set_album(album.clone());
+        }
+        if let Some(track) = self.track
+        {
+            tag.set_track(track);
+        }
+        if let Some(year) = self.year
+        {
+            let year = i32::try_from(year).unwrap_or(i32::MAX);
+            // ID3v2.4 has the year only as part of the recording date.
+            match tag.date_recorded()
+            {
+                Some(date) => tag.set_date_recorded(Timestamp { year, ..date }),
+                None if tag.version() == Version::Id3v24 => tag.set_date_recorded(Timestamp {
+                    year,
+                    month: None,
+                    day: None,
+                    hour: None,
+                    minute: None,
+                    second: None,
+                }),
+                None => tag.set_year(year),
+            }
+        }
+    }
+}
+
+// ID3 tags are changed frame by frame with `id3`, since lofty's
+// generic tag doesn't have all frames (e.g. SYLT).
+fn write_id3(path: &Path, edit: &TagEdit) -> Result<(), Error>
+{
+    let mut tag = match id3::Tag::read_from_path(path)
+    {
+        Ok(tag) => tag,
+        Err(err) if matches!(err.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    edit.apply_id3(&mut tag);
+    tag.write_to_path(path, tag.version())?;
+
+    Ok(())
+}
+
+// Reads the song as the file type it is, lets `f` change its tag and
+// writes all tags back as they were read, so that nothing is lost.
+fn rewrite<F, E>(path: &Path, f: E) -> Result<(), Error>
+where
+    F: AudioFile,
+    E: FnOnce(&mut F),
+{
+    let mut file = F::read_from(&mut File::open(path)?, ParseOptions::new())?;
+    f(&mut file);
+    file.save_to_path(path, WriteOptions::default())?;
+
+    Ok(())
+}
+
+fn write_into(path: &Path, file_type: Option<FileType>, edit: &TagEdit) -> Result<(), Error>
+{
+    match file_type
+    {
+        Some(FileType::Mpeg | FileType::Aac | FileType::Wav | FileType::Aiff) =>
+        {
+            write_id3(path, edit)
+        }
+        Some(FileType::Flac) => rewrite(path, |file: &mut FlacFile| {
+            let mut tag = file.remove_vorbis_comments().unwrap_or_default();
+            edit.apply(&mut tag);
+            file.set_vorbis_comments(tag);
+        }),
+        Some(FileType::Vorbis) => rewrite(path, |file: &mut VorbisFile| {
+            edit.apply(file.vorbis_comments_mut());
+        }),
+        Some(FileType::Opus) => rewrite(path, |file: &mut OpusFile| {
+            edit.apply(file.vorbis_comments_mut());
+        }),
+        Some(FileType::Speex) => rewrite(path, |file: &mut SpeexFile| {
+            edit.apply(file.vorbis_comments_mut());
+        }),
+        Some(FileType::Mp4) => rewrite(path, |file: &mut Mp4File| {
+            let mut tag = file.remove_ilst().unwrap_or_default();
+            edit.apply(&mut tag);
+            file.set_ilst(tag);
+        }),
+        Some(FileType::Ape) => rewrite(path, |file: &mut ApeFile| {
+            let mut tag = file.remove_ape().unwrap_or_default();
+            edit.apply(&mut tag);
+            file.set_ape(tag);
+        }),
+        Some(FileType::Mpc) => rewrite(path, |file: &mut MpcFile| {
+            let mut tag = file.remove_ape().unwrap_or_default();
+            edit.apply(&mut tag);
+            file.set_ape(tag);
+        }),
+        Some(FileType::WavPack) => rewrite(path, |file: &mut WavPackFile| {
+            let mut tag = file.remove_ape().unwrap_or_default();
+            edit.apply(&mut tag);
+            file.set_ape(tag);
+        }),
+        _ => Err(LoftyError::new(ErrorKind::UnknownFormat).into()),
+    }
+}
+
+// The song is still read while it's played, so the tags are written
+// into a copy which then replaces it.
+fn write_tags(path: &Path, edit: &TagEdit) -> Result<(), Error>
+{
+    // The copy's name says nothing about its type anymore.
+    let file_type = Probe::open(path)?.guess_file_type()?.file_type();
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".legacylisten");
+    let copy = path.with_file_name(name);
+
+    fs::copy(path, &copy)?;
+    let written = write_into(&copy, file_type, edit)
+        .and_then(|()| fs::rename(&copy, path).map_err(Error::from));
+    if written.is_err()
+    {
+        let _ = fs::remove_file(&copy);
+    }
+
+    written
+}
+
+impl Config
+{
+    /// Writes what was typed for `Command::EditTags` into the current
+    /// song and shows it everywhere.
+    pub fn edit_tags(&mut self, edit: &TagEdit)
+    {
+        if edit.is_empty()
+        {
+            self.l10n.write(Message::TagsUnchanged);
+            return;
+        }
+        if edit.song != self.arc_config.active_song.load(Ordering::SeqCst)
+        {
+            self.l10n.write(Message::SongChangedWhileEditing);
+            return;
+        }
+
+        self.read_tags();
+        let path = match &self.metadata
+        {
+            Some(Ok(metadata)) => metadata.path.clone(),
+            Some(Err(err)) =>
+            {
+                self.l10n
+                    .write(Message::MetadataUnsupported(err.to_string()));
+                return;
+            }
+            None =>
+            {
+                self.l10n.write(Message::NothingPlayingYet);
+                return;
+            }
+        };
+
+        match write_tags(&path, edit)
+        {
+            Ok(()) =>
+            {
+                // Updates the index as well.
+                self.index_metadata(&path);
+                // MPRIS clients read the metadata again.
+                self.arc_config.update_dbus.store(true, Ordering::SeqCst);
+                self.l10n.write(Message::TagsSaved);
+            }
+            Err(err) => self.l10n.write(Message::TagsNotSaved(err.to_string())),
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use std::{
-    convert::TryFrom,
     io::{self, Read},
     path::PathBuf,
     sync::{
+        atomic::Ordering,
         mpsc::{Receiver, Sender},
         Arc,
     },
@@ -10,7 +10,6 @@ use std::{
     time::Duration,
 };
 
-use id3::Tag;
 use nix::sys::sysinfo::sysinfo;
 use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
 use signal_hook::iterator::Signals;
@@ -20,26 +19,68 @@ use crate::{
     config::ArcConfig,
     dbus::handle_mpris,
     l10n::{messages::Message, L10n},
+    metadata::Metadata,
+    tagging::read_tag_edit,
 };
 
-fn input_handler(tx: &Sender<Command>, l10n: L10n)
+fn input_handler(tx: &Sender<Command>, config: &Arc<ArcConfig>, l10n: L10n)
 {
     let mut buf = [0];
+    // A number typed directly before a command is its argument
+    // (currently only used by `Command::SeekTo`, `Command::SetRate`,
+    // `Command::QuitAfterTime` and `Command::PauseAfterTime`).
+    let mut number = None::<usize>;
 
     l10n.write(Message::HelpNotice);
 
-    while let Ok(num) = io::stdin().lock().read(&mut buf)
+    while let Ok(num) = io::stdin().read(&mut buf)
     {
         if num == 1
         {
+            if buf[0].is_ascii_digit()
+            {
+                number = Some(
+                    number
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add((buf[0] - b'0') as usize),
+                );
+                continue;
+            }
+
+            let arg = number.take();
+
             if buf[0] == b'?'
             {
                 Command::show_help(l10n);
             }
-            else if buf[0] >= b'a' && buf[0] <= b'z'
+            else if buf[0].is_ascii_alphabetic()
             {
-                if let Ok(com) = Command::try_from(buf[0] - b'a')
+                if let Some(com) = Command::from_char(buf[0])
                 {
+                    match com
+                    {
+                        Command::SeekTo => config
+                            .seek_target
+                            .store(arg.unwrap_or(0).saturating_mul(1_000_000), Ordering::SeqCst),
+                        Command::SetRate => config.rate_target.store(
+                            (arg.unwrap_or(100) as f32 / 100.0).to_bits(),
+                            Ordering::SeqCst,
+                        ),
+                        Command::QuitAfterTime | Command::PauseAfterTime => config
+                            .sleep_minutes
+                            .store(arg.unwrap_or(0), Ordering::SeqCst),
+                        Command::EditTags =>
+                        {
+                            let edit = read_tag_edit(config, l10n);
+                            if let Ok(mut tag_edit) = config.tag_edit.lock()
+                            {
+                                *tag_edit = Some(edit);
+                            }
+                        }
+                        _ =>
+                        {}
+                    }
                     let _ = tx.send(com);
                 }
                 else
@@ -79,7 +120,7 @@ fn mpris_handler(
     tx: &Sender<Command>,
     tx_control: &Sender<()>,
     rx_paused: Receiver<bool>,
-    rx_path: Receiver<(PathBuf, Option<Tag>)>,
+    rx_path: Receiver<(PathBuf, Option<Metadata>)>,
     config: &Arc<ArcConfig>,
     l10n: L10n,
 )
@@ -123,7 +164,7 @@ pub fn start_threads(
     tx: Sender<Command>,
     tx_control: Sender<()>,
     rx_paused: Receiver<bool>,
-    rx_path: Receiver<(PathBuf, Option<Tag>)>,
+    rx_path: Receiver<(PathBuf, Option<Metadata>)>,
     signals: Signals,
     config: Arc<ArcConfig>,
     l10n: L10n,
@@ -135,9 +176,10 @@ pub fn start_threads(
     let tx4 = tx;
     let config1 = config.clone();
     let config2 = config.clone();
-    let config3 = config;
+    let config3 = config.clone();
+    let config4 = config;
 
-    let _ = thread::spawn(move || input_handler(&tx1, l10n));
+    let _ = thread::spawn(move || input_handler(&tx1, &config4, l10n));
     let _ = thread::spawn(move || signal_handler(&tx2, signals, &config1, l10n));
     let _ =
         thread::spawn(move || mpris_handler(&tx3, &tx_control, rx_paused, rx_path, &config2, l10n));